@@ -0,0 +1,50 @@
+//! Talking to the outside world through the console device.
+//!
+//! The guest copies its input to its output, upper-casing letters on the way, until the
+//! input runs out. The console reads from an in-memory buffer and writes to another one
+//! here, `Console::stdio()` wires it to the terminal instead.
+//!
+//! cargo run --example console
+
+use std::io::Cursor;
+
+use anyhow::Result;
+
+use vm::{
+    assembler::assemble,
+    devices::console::{Console, SharedBuffer, CONSOLE_BASE, CONSOLE_SIZE},
+    Machine,
+};
+
+const PROGRAM: &str = "
+loop:
+    LoadMemory A 0xE001         ; status port
+    AndI A 1
+    JumpConditional EQ done     ; no input left
+    LoadMemory A 0xE000         ; data port
+    CmpI A 97                   ; 'a'
+    JumpConditional LT print
+    CmpI A 123                  ; 'z' + 1
+    JumpConditional GE print
+    SubI A 32
+print:
+    Store A 0xE000
+    Jump loop
+done:
+    Halt
+";
+
+fn main() -> Result<()> {
+    let output = SharedBuffer::new();
+    let console = Console::new(Cursor::new("hello, byte machine!\n"), output.clone());
+
+    let mut vm = Machine::new();
+    vm.bus.map(CONSOLE_BASE, CONSOLE_SIZE, Box::new(console))?;
+    vm.bus.load(&assemble(PROGRAM)?)?;
+    while !vm.halt {
+        vm.step()?;
+    }
+
+    print!("{}", String::from_utf8_lossy(&output.bytes()));
+    Ok(())
+}
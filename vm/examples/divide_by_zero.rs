@@ -0,0 +1,51 @@
+//! Recovering from a guest division by zero.
+//!
+//! The guest divides 100 by 0, 1, 2 and 3 and stores the quotients from 0x1000 on. Division
+//! by zero doesn't take the host down: `step` hands back `VmError::DivideByZero` with PC
+//! still on the `ALU Div`, the host saturates the quotient to 255, skips the instruction and
+//! lets the program carry on.
+//!
+//! cargo run --example divide_by_zero
+
+use anyhow::Result;
+
+use vm::{assembler::assemble, interrupts::halt_interrupt, Machine, Registers, VmError};
+
+const PROGRAM: &str = "
+LoadImmediate C 0x10
+LoadImmediate D 0
+loop:
+    LoadImmediate A 100
+    ALU Div A D
+    Store A [C:D]+
+    LoadImmediate B 4
+    ALU Cmp D B
+    JumpConditional LT loop
+    Interrupt 15
+";
+
+// ALU instructions are always two bytes long
+const ALU_SIZE: u16 = 2;
+
+fn main() -> Result<()> {
+    let mut vm = Machine::new();
+    vm.define_interrupt(0xF, halt_interrupt);
+    vm.bus.load(&assemble(PROGRAM)?)?;
+
+    while !vm.halt {
+        match vm.step() {
+            Ok(()) => {}
+            Err(VmError::DivideByZero { pc, opcode }) => {
+                println!("division by zero (opcode 0x{opcode:02X}) @ 0x{pc:04X}, recovering");
+                vm.set_register(Registers::A, 0xFF);
+                vm.set_register(Registers::PC, pc + ALU_SIZE);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    for divisor in 0..4 {
+        println!("100 / {} = {}", divisor, vm.bus.read(0x1000 + divisor)?);
+    }
+    Ok(())
+}
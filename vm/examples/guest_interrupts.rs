@@ -0,0 +1,40 @@
+//! Interrupt handlers written in the guest.
+//!
+//! The program installs `count` as the handler of `Interrupt 1` in the vector table and
+//! enables interrupts, so `Interrupt 1` runs guest code and `Iret` comes back. `Interrupt 2`
+//! has no vector, so it falls back to the host handler printing A.
+//!
+//! cargo run --example guest_interrupts
+
+use anyhow::Result;
+
+use vm::{assembler::assemble, Machine, Registers};
+
+const PROGRAM: &str = "
+    LoadImmediate C:D count     ; vector 1 lives at 0xEFE2, low byte first
+    Store D 0xEFE2
+    Store C 0xEFE3
+    Ei
+    LoadImmediate A 0
+    Interrupt 1
+    Interrupt 1
+    Interrupt 1
+    Interrupt 2
+    Halt
+count:
+    AddI A 1
+    Iret
+";
+
+fn main() -> Result<()> {
+    let mut vm = Machine::new();
+    vm.define_interrupt(0x2, |vm: &mut Machine| {
+        println!("host handler: A = {}", vm.get_register(Registers::A));
+        Ok(())
+    });
+    vm.bus.load(&assemble(PROGRAM)?)?;
+    while !vm.halt {
+        vm.step()?;
+    }
+    Ok(())
+}
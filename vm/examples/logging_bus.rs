@@ -0,0 +1,50 @@
+//! Plugging a custom bus into the machine.
+//!
+//! `LoggingBus` keeps RAM in a sparse map, so only the bytes a program touches take up host
+//! memory, and prints every write. Devices and memory protection still work on top of it.
+//!
+//! cargo run --example logging_bus
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use vm::{assembler::assemble, Bus, Machine, VmError};
+
+#[derive(Default)]
+struct LoggingBus {
+    bytes: HashMap<u16, u8>,
+}
+
+impl Bus for LoggingBus {
+    fn read(&mut self, addr: u16) -> Result<u8, VmError> {
+        Ok(self.bytes.get(&addr).copied().unwrap_or(0))
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), VmError> {
+        println!("write 0x{value:02X} @ 0x{addr:04X}");
+        self.bytes.insert(addr, value);
+        Ok(())
+    }
+
+    /// Lets the machine take snapshots and keep a journal without logging anything.
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.bytes.get(&addr).copied().unwrap_or(0))
+    }
+}
+
+const PROGRAM: &str = "
+    LoadImmediate A 0x2A
+    Store A 0xFFFF
+    PushRegister A
+    Halt
+";
+
+fn main() -> Result<()> {
+    let mut vm = Machine::with_bus(LoggingBus::default());
+    vm.bus.load(&assemble(PROGRAM)?)?;
+    while !vm.halt {
+        vm.step()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! Running `code.naked` to the end and going back in time.
+//!
+//! With the journal on, the counter at 0xfffc can be watched going back down while the
+//! machine steps back to the start of the loop, then all the way to the first instruction.
+//!
+//! cargo run --example reverse
+
+use anyhow::Result;
+
+use vm::{assembler::assemble, interrupts::halt_interrupt, Machine};
+
+fn main() -> Result<()> {
+    let mut vm = Machine::new();
+    vm.define_interrupt(0xF, halt_interrupt);
+    vm.bus.load(&assemble(include_str!("../code.naked"))?)?;
+    vm.start_journal(1000)?;
+
+    while !vm.halt {
+        vm.step()?;
+    }
+    println!(
+        "halted after {} steps, counter = {:?}",
+        vm.cycles(),
+        vm.bus.peek(0xfffc)
+    );
+
+    // the loop starts right after the three setup instructions
+    let start = 9;
+    while vm.run_back_to(start)? {
+        println!(
+            "back at 0x{start:04X} after {} steps, counter = {:?}",
+            vm.cycles(),
+            vm.bus.peek(0xfffc)
+        );
+    }
+    println!("history starts at 0x{:04X}: {}", vm.pc(), vm.state());
+    Ok(())
+}
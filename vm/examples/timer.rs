@@ -0,0 +1,55 @@
+//! A periodic timer interrupting a busy loop.
+//!
+//! The timer raises IRQ line 0 every 50 instructions. The main loop just counts in A, the
+//! guest handler of interrupt 0 counts ticks in B and the program stops after 5 of them.
+//!
+//! cargo run --example timer
+
+use anyhow::Result;
+
+use vm::{
+    assembler::assemble,
+    devices::{
+        timer::{TIMER_BASE, TIMER_SIZE},
+        Timer,
+    },
+    Machine, Registers,
+};
+
+const PROGRAM: &str = "
+    LoadImmediate C:D tick      ; vector 0 at 0xEFE0
+    Store D 0xEFE0
+    Store C 0xEFE1
+    LoadImmediate A 50          ; reload value
+    Store A 0xE008
+    LoadImmediate A 3           ; enabled, periodic
+    Store A 0xE00A
+    LoadImmediate A 0
+    LoadImmediate B 0
+    Ei
+busy:
+    AddI A 1
+    CmpI B 5
+    JumpConditional LT busy
+    Halt
+tick:
+    AddI B 1
+    Iret
+";
+
+fn main() -> Result<()> {
+    let mut vm = Machine::new();
+    vm.bus
+        .map(TIMER_BASE, TIMER_SIZE, Box::new(Timer::new(0)))?;
+    vm.bus.load(&assemble(PROGRAM)?)?;
+    while !vm.halt {
+        vm.step()?;
+    }
+    println!(
+        "{} ticks, {} loops in {} cycles",
+        vm.get_register(Registers::B),
+        vm.get_register(Registers::A),
+        vm.cycles()
+    );
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Finding out which instruction writes an address.
+//!
+//! `code.naked` keeps its counter at 0xfffc. A stopping watchpoint on writes to it makes
+//! `step` return after every such write, with the instruction and the old and new value,
+//! and execution carries on from there. A second watchpoint just counts the reads.
+//!
+//! cargo run --example watchpoint
+
+use std::{cell::Cell, rc::Rc};
+
+use anyhow::Result;
+
+use vm::{assembler::assemble, interrupts::halt_interrupt, Access, Machine, VmError, WatchAction};
+
+fn main() -> Result<()> {
+    let mut vm = Machine::new();
+    vm.define_interrupt(0xF, halt_interrupt);
+    vm.bus.load(&assemble(include_str!("../code.naked"))?)?;
+
+    vm.watch(0xfffc, 1, Access::Write, WatchAction::Stop);
+    let reads = Rc::new(Cell::new(0));
+    let counter = reads.clone();
+    vm.watch(
+        0xfffc,
+        1,
+        Access::Read,
+        WatchAction::Call(Box::new(move |_| counter.set(counter.get() + 1))),
+    );
+
+    while !vm.halt {
+        match vm.step() {
+            Ok(()) => {}
+            Err(err @ VmError::Watchpoint { .. }) => println!("{err}"),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    println!("0xfffc was read {} times", reads.get());
+    Ok(())
+}
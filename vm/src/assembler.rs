@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use crate::{ALUOperation, Address, Instruction, JumpCondition, JumpTarget, Registers, VmError};
+
+trait LocalToAsm {
+    fn from(instruction: Vec<&str>) -> Result<Self, String>
+    where
+        Self: Sized;
+}
+
+impl LocalToAsm for Instruction {
+    fn from(parts: Vec<&str>) -> Result<Self, String> {
+        match *parts
+            .first()
+            .ok_or_else(|| "where's the instruction you dumbass!".to_string())?
+        {
+            "Nop" => Ok(Instruction::Nop),
+            "Push" => {
+                let value = parts
+                    .get(1)
+                    .ok_or_else(|| "Push instruction requires a value".to_string())?
+                    .parse::<u8>()
+                    .map_err(|_| "Invalid value for Push instruction".to_string())?;
+                Ok(Instruction::Push(value))
+            }
+            "PopRegister" => {
+                let register = parts
+                    .get(1)
+                    .ok_or_else(|| "PopRegister instruction requires a register".to_string())?;
+                let reg = Registers::from_str_custom(register)
+                    .ok_or_else(|| "Invalid register for PopRegister instruction".to_string())?;
+                Ok(Instruction::PopRegister(reg))
+            }
+            "PushRegister" => {
+                let register = parts
+                    .get(1)
+                    .ok_or_else(|| "PopRegister instruction requires a register".to_string())?;
+                let reg = Registers::from_str_custom(register)
+                    .ok_or_else(|| "Invalid register for PopRegister instruction".to_string())?;
+                Ok(Instruction::PushRegister(reg))
+            }
+            "AddStack" => Ok(Instruction::AddStack),
+            "LoadImmediate" => {
+                let reg = parts
+                    .get(1)
+                    .ok_or_else(|| "LoadImmediate instruction requires a register".to_string())
+                    .and_then(|reg_str| {
+                        Registers::from_str_custom(reg_str).ok_or_else(|| {
+                            "Invalid register for LoadImmediate instruction".to_string()
+                        })
+                    })?;
+
+                let value_str = parts
+                    .get(2)
+                    .ok_or_else(|| "Push instruction requires a value".to_string())?;
+                let value = u16::from_str_radix(
+                    value_str.strip_prefix("0x").unwrap_or(value_str),
+                    if value_str.starts_with("0x") { 16 } else { 10 },
+                )
+                .ok()
+                .filter(|value| *value <= reg.mask())
+                .ok_or_else(|| {
+                    if reg.is_wide() {
+                        "LoadImmediate expect a u16".to_string()
+                    } else {
+                        "LoadImmediate expect a u8".to_string()
+                    }
+                })?;
+
+                Ok(Instruction::LoadImmediate(reg, value))
+            }
+            "LoadMemory" => {
+                let reg = parts
+                    .get(1)
+                    .ok_or_else(|| "LoadMemory instruction requires a register".to_string())
+                    .and_then(|reg_str| {
+                        // register pairs have no LoadMemory encoding
+                        Registers::from_str_custom(reg_str)
+                            .filter(|reg| reg.halves().is_none())
+                            .ok_or_else(|| {
+                                "Invalid register for AddRegister instruction".to_string()
+                            })
+                    })?;
+
+                let memory_str = parts.get(2).ok_or_else(|| {
+                    "LoadMemory instruction requires a memory address".to_string()
+                })?;
+                if memory_str.starts_with('[') {
+                    let address = Address::from_str_custom(memory_str).ok_or_else(|| {
+                        "Invalid addressing mode for LoadMemory instruction".to_string()
+                    })?;
+                    return Ok(Instruction::LoadIndirect(reg, address));
+                }
+                let memory = u16::from_str_radix(
+                    memory_str.strip_prefix("0x").unwrap_or(memory_str),
+                    if memory_str.starts_with("0x") { 16 } else { 10 },
+                )
+                .map_err(|_| "Invalid address for LoadImmediate instruction".to_string())?;
+
+                Ok(Instruction::LoadMemory(reg, memory))
+            }
+            "Store" => {
+                let reg = parts
+                    .get(1)
+                    .ok_or_else(|| "Store instruction requires a register".to_string())
+                    .and_then(|reg_str| {
+                        Registers::from_str_custom(reg_str)
+                            .filter(|reg| reg.halves().is_none())
+                            .ok_or_else(|| "Invalid register for Store instruction".to_string())
+                    })?;
+
+                let memory_str = parts
+                    .get(2)
+                    .ok_or_else(|| "Store instruction requires a memory address".to_string())?;
+                if memory_str.starts_with('[') {
+                    let address = Address::from_str_custom(memory_str).ok_or_else(|| {
+                        "Invalid addressing mode for Store instruction".to_string()
+                    })?;
+                    return Ok(Instruction::StoreIndirect(reg, address));
+                }
+                let memory = u16::from_str_radix(
+                    memory_str.strip_prefix("0x").unwrap_or(memory_str),
+                    if memory_str.starts_with("0x") { 16 } else { 10 },
+                )
+                .map_err(|_| "Invalid address for Store instruction".to_string())?;
+
+                // println!("{:?}, {}", reg, memory);
+
+                Ok(Instruction::Store(reg, memory))
+            }
+            "ALU" => {
+                let operation = parts
+                    .get(1)
+                    .ok_or_else(|| "ALU instruction requires a operation".to_string())
+                    .and_then(|operation_str| {
+                        ALUOperation::from_str_custom(operation_str)
+                            .ok_or_else(|| "Invalid Operation for ALU".to_string())
+                    })?;
+
+                let reg1 = parts
+                    .get(2)
+                    .ok_or_else(|| "Where's the 1st register dawg?".to_string())
+                    .and_then(|reg_str| {
+                        Registers::from_str_custom(reg_str)
+                            .ok_or_else(|| "Invalid first register for ALU instruction".to_string())
+                    })?;
+                let reg2 = match parts.get(3).filter(|part| !part.starts_with(';')) {
+                    // Not only has the one operand, the second register can be left out
+                    None if matches!(operation, ALUOperation::Not) => {
+                        return Ok(Instruction::ALU(operation, reg1, reg1));
+                    }
+                    reg2 => reg2,
+                };
+                let reg2 = reg2
+                    .ok_or_else(|| "Where's the 2nd register dawg?".to_string())
+                    .and_then(|reg_str| {
+                        Registers::from_str_custom(reg_str).ok_or_else(|| {
+                            "Invalid second register for ALU instruction".to_string()
+                        })
+                    })?;
+
+                // println!("{:?}, {:?}, {:?}", operation, reg1, reg2);
+
+                Ok(Instruction::ALU(operation, reg1, reg2))
+            }
+            "Jump" => {
+                let target = parts
+                    .get(1)
+                    .ok_or_else(|| "Jump instruction requires a target".to_string())?;
+
+                let jump_target = if let Ok(address) = u16::from_str_radix(
+                    target.strip_prefix("0x").unwrap_or(target),
+                    if target.starts_with("0x") { 16 } else { 10 },
+                ) {
+                    JumpTarget::Address(address)
+                } else {
+                    JumpTarget::Label(target.to_string())
+                };
+                Ok(Instruction::Jump(jump_target))
+            }
+            "JumpConditional" => {
+                let condition = parts
+                    .get(1)
+                    .ok_or_else(|| "JumpConditional instruction requires a Condition".to_string())
+                    .and_then(|conditional_str| {
+                        JumpCondition::from_str_custom(conditional_str)
+                            .ok_or_else(|| "Invalid Condition for JumpConditional".to_string())
+                    })?;
+
+                let target = parts
+                    .get(2)
+                    .ok_or_else(|| "JumpConditional instruction requires a target".to_string())?;
+
+                let jump_target = if let Ok(address) = u16::from_str_radix(
+                    target.strip_prefix("0x").unwrap_or(target),
+                    if target.starts_with("0x") { 16 } else { 10 },
+                ) {
+                    JumpTarget::Address(address)
+                } else {
+                    JumpTarget::Label(target.to_string())
+                };
+
+                Ok(Instruction::JumpConditional(condition, jump_target))
+            }
+            "Call" => {
+                let target = parts
+                    .get(1)
+                    .ok_or_else(|| "Call instruction requires a target".to_string())?;
+
+                let call_target = if let Ok(address) = u16::from_str_radix(
+                    target.strip_prefix("0x").unwrap_or(target),
+                    if target.starts_with("0x") { 16 } else { 10 },
+                ) {
+                    JumpTarget::Address(address)
+                } else {
+                    JumpTarget::Label(target.to_string())
+                };
+                Ok(Instruction::Call(call_target))
+            }
+            "Ret" => Ok(Instruction::Ret),
+            "Enter" => {
+                let size = parts
+                    .get(1)
+                    .ok_or_else(|| "Enter instruction requires a frame size".to_string())?
+                    .parse::<u8>()
+                    .map_err(|_| "Invalid frame size for Enter instruction".to_string())?;
+                Ok(Instruction::Enter(size))
+            }
+            "Leave" => Ok(Instruction::Leave),
+            "Halt" => Ok(Instruction::Halt),
+            "Iret" => Ok(Instruction::Iret),
+            "Ei" => Ok(Instruction::Ei),
+            "Di" => Ok(Instruction::Di),
+            "Move" => {
+                let dst = parts
+                    .get(1)
+                    .ok_or_else(|| "Move instruction requires a destination register".to_string())
+                    .and_then(|reg_str| {
+                        Registers::from_str_custom(reg_str)
+                            .ok_or_else(|| "Invalid destination register for Move".to_string())
+                    })?;
+                let src = parts
+                    .get(2)
+                    .ok_or_else(|| "Move instruction requires a source register".to_string())
+                    .and_then(|reg_str| {
+                        Registers::from_str_custom(reg_str)
+                            .ok_or_else(|| "Invalid source register for Move".to_string())
+                    })?;
+                Ok(Instruction::Move(dst, src))
+            }
+            "Interrupt" => {
+                let value = parts
+                    .get(1)
+                    .ok_or_else(|| "Interrupt instruction requires a value".to_string())?
+                    .parse::<u8>()
+                    .map_err(|_| "Invalid value for interrupt instruction".to_string())?;
+                Ok(Instruction::Interrupt(value))
+            }
+            // AddI, SubI, CmpI, ... are the ALU operations with an immediate second operand,
+            // NotI ignores it but decodes all the same so it assembles too
+            err => match err
+                .strip_suffix('I')
+                .and_then(ALUOperation::from_str_custom)
+            {
+                Some(operation) => {
+                    let reg = parts
+                        .get(1)
+                        .ok_or_else(|| format!("{err} instruction requires a register"))
+                        .and_then(|reg_str| {
+                            Registers::from_str_custom(reg_str)
+                                .ok_or_else(|| format!("Invalid register for {err} instruction"))
+                        })?;
+                    let value_str = parts
+                        .get(2)
+                        .ok_or_else(|| format!("{err} instruction requires a value"))?;
+                    let value = u8::from_str_radix(
+                        value_str.strip_prefix("0x").unwrap_or(value_str),
+                        if value_str.starts_with("0x") { 16 } else { 10 },
+                    )
+                    .map_err(|_| format!("{err} expect a u8"))?;
+                    Ok(Instruction::ALUImmediate(operation, reg, value))
+                }
+                _ => Err(format!(
+                    "You're a piece of shit.(from Assmeber), unexpected '{}'",
+                    err
+                )),
+            },
+        }
+    }
+}
+
+/// `LoadImmediate` of a 16-bit register also takes a label, say to put the address of an
+/// interrupt handler in the vector table. Gives the address to use in place of the label, 0
+/// on the first pass when the label may not have been seen yet.
+fn label_immediate(
+    parts: &[&str],
+    labels: &HashMap<String, u16>,
+    first_pass: bool,
+) -> Result<Option<String>, String> {
+    let ["LoadImmediate", register, value, ..] = parts else {
+        return Ok(None);
+    };
+    let wide = Registers::from_str_custom(register).is_some_and(|reg| reg.is_wide());
+    if !wide || value.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    match labels.get(*value) {
+        Some(address) => Ok(Some(address.to_string())),
+        None if first_pass => Ok(Some("0".to_string())),
+        None => Err(format!(
+            "Unresolved label in LoadImmediate instruction = {value}"
+        )),
+    }
+}
+
+/// The address of every label in `source`, once assembled.
+pub fn labels(source: &str) -> Result<HashMap<String, u16>, VmError> {
+    let mut labels = HashMap::new();
+    let mut current_address = 0u16;
+
+    for (number, line) in source.lines().enumerate() {
+        // println!("{current_address}");
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            labels.insert(label.to_string(), current_address);
+        } else {
+            let mut parts: Vec<&str> = line.split_whitespace().collect();
+            let error = |reason| VmError::Assemble {
+                line: number + 1,
+                reason,
+            };
+            let address = label_immediate(&parts, &labels, true).map_err(error)?;
+            if let Some(address) = &address {
+                parts[2] = address;
+            }
+            let instruction = <Instruction as LocalToAsm>::from(parts).map_err(error)?;
+            current_address += instruction.size();
+        }
+    }
+    // println!("current address = {current_address}");
+    Ok(labels)
+}
+
+/// Assembles `source` into the bytes the vm understands.
+///
+/// Labels are resolved in two passes: the first one (`labels`) just records the address of
+/// every label, the second one swaps jump targets for those addresses and encodes.
+pub fn assemble(source: &str) -> Result<Vec<u8>, VmError> {
+    let lines: Vec<&str> = source.lines().collect();
+    let labels = labels(source)?;
+
+    let mut bytes: Vec<u8> = Vec::new();
+    for (number, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.ends_with(':') {
+            continue;
+        }
+
+        let mut parts: Vec<&str> = line.split_whitespace().collect();
+        let error = |reason| VmError::Assemble {
+            line: number + 1,
+            reason,
+        };
+        let address = label_immediate(&parts, &labels, false).map_err(error)?;
+        if let Some(address) = &address {
+            parts[2] = address;
+        }
+        let mut result = <Instruction as LocalToAsm>::from(parts).map_err(error)?;
+
+        if let Instruction::Jump(ref mut address)
+        | Instruction::JumpConditional(_, ref mut address)
+        | Instruction::Call(ref mut address) = result
+        {
+            match address {
+                JumpTarget::Label(label) => {
+                    if let Some(&label_address) = labels.get(&label.to_string()) {
+                        *address = JumpTarget::Address(label_address);
+                    }
+                }
+                JumpTarget::Address(addr) => *address = JumpTarget::Address(*addr),
+            }
+        }
+
+        let encoded = result.encode().map_err(|err| error(err.to_string()))?;
+        bytes.extend(encoded);
+    }
+    Ok(bytes)
+}
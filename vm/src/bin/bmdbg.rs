@@ -0,0 +1,332 @@
+use anyhow::Result;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use vm::assembler::{assemble, labels};
+use vm::devices::Console;
+use vm::{Instruction, Machine, Registers, VmError, MAX_INSTRUCTION_SIZE};
+
+const USAGE: &str = "usage: bmdbg PROGRAM (assembled, or a .naked source to get its labels)";
+
+const HELP: &str = "\
+break ADDR|LABEL    stop before the instruction at ADDR (b)
+delete ADDR|LABEL   remove a breakpoint (d)
+breaks              list the breakpoints
+step [N]            execute N instructions, 1 by default (s)
+next                like step, but runs a Call until it returns (n)
+continue            run until a breakpoint, halt or fault (c)
+run                 run until halt or fault, ignoring the breakpoints
+                    (running stops every 1000000 steps, continue to go on)
+regs                show the registers (r)
+set REG VALUE       write a register, named like in the assembler (A, SP, A:B, ...)
+x ADDR [LEN]        dump LEN bytes of memory, 16 by default
+poke ADDR BYTE...   write bytes to memory, ignoring the memory protection
+disas [ADDR] [N]    disassemble N instructions, around PC by default (l)
+labels              list the labels of the program
+help                show this (h)
+quit                leave (q)
+An empty line repeats the last command. Numbers are decimal or 0x hex.";
+
+/// Steps running goes before handing back the prompt, so a program that never halts can't
+/// hang the debugger.
+const RUN_LIMIT: u64 = 1_000_000;
+
+/// Why running stopped.
+enum Stop {
+    Done,
+    Limit,
+    Breakpoint,
+    Halted,
+    Fault(VmError),
+}
+
+struct Debugger {
+    vm: Machine,
+    labels: HashMap<String, u16>,
+    breakpoints: BTreeSet<u16>,
+}
+
+/// Decimal, or hex with a 0x in front.
+fn number(text: &str) -> Result<u16> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    value.map_err(|_| anyhow::anyhow!("not a number: {text}"))
+}
+
+impl Debugger {
+    /// An address or the name of a label.
+    fn address(&self, text: &str) -> Result<u16> {
+        match self.labels.get(text) {
+            Some(address) => Ok(*address),
+            None => number(text).map_err(|_| anyhow::anyhow!("no address or label {text}")),
+        }
+    }
+
+    fn decode(&self, addr: u16) -> Option<(Instruction, u16)> {
+        let bytes: Vec<u8> = (0..MAX_INSTRUCTION_SIZE)
+            .map_while(|offset| self.vm.bus.peek(addr.wrapping_add(offset)))
+            .collect();
+        let (instruction, size) = Instruction::decode(&bytes).ok()?;
+        Some((instruction, size as u16))
+    }
+
+    /// The instruction at `addr` as a line of disassembly, and its size.
+    fn line(&self, addr: u16) -> (String, u16) {
+        let (text, size) = match self.decode(addr) {
+            Some((instruction, size)) => (instruction.to_string(), size),
+            None => match self.vm.bus.peek(addr) {
+                Some(byte) => (format!(".byte 0x{byte:02X}"), 1),
+                None => ("??".to_string(), 1),
+            },
+        };
+        let bytes = (0..size)
+            .map(|offset| match self.vm.bus.peek(addr.wrapping_add(offset)) {
+                Some(byte) => format!("{byte:02X}"),
+                None => "--".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let pc = if addr == self.vm.pc() { "=>" } else { "  " };
+        let breakpoint = if self.breakpoints.contains(&addr) {
+            '*'
+        } else {
+            ' '
+        };
+        (
+            format!("{pc}{breakpoint} 0x{addr:04X}  {bytes:<12} {text}"),
+            size,
+        )
+    }
+
+    /// Where to start disassembling to show `before` instructions ahead of `pc`.
+    ///
+    /// Instructions can only be told apart walking forward from one, so this walks from the
+    /// start of the program and gives up (starting at `pc`) if it doesn't land on `pc`.
+    fn before(&self, pc: u16, before: usize) -> u16 {
+        let mut starts = Vec::new();
+        let mut addr = 0u16;
+        while addr < pc {
+            starts.push(addr);
+            let (_, size) = self.line(addr);
+            addr = match addr.checked_add(size) {
+                Some(next) => next,
+                None => return pc,
+            };
+        }
+        if addr != pc {
+            return pc;
+        }
+        starts
+            .len()
+            .checked_sub(before)
+            .map_or(0, |index| starts[index])
+    }
+
+    fn disassemble(&self, mut addr: u16, count: usize) {
+        for _ in 0..count {
+            for (label, _) in self.labels.iter().filter(|(_, at)| **at == addr) {
+                println!("{label}:");
+            }
+            let (line, size) = self.line(addr);
+            println!("{line}");
+            addr = addr.wrapping_add(size);
+        }
+    }
+
+    /// Steps until `done` says so, a breakpoint (if `breakpoints`), a halt, a fault or
+    /// `RUN_LIMIT` steps.
+    fn run(&mut self, breakpoints: bool, mut done: impl FnMut(&Machine) -> bool) -> Stop {
+        for _ in 0..RUN_LIMIT {
+            if self.vm.halt {
+                return Stop::Halted;
+            }
+            if let Err(err) = self.vm.step() {
+                return Stop::Fault(err);
+            }
+            if self.vm.halt {
+                return Stop::Halted;
+            }
+            if done(&self.vm) {
+                return Stop::Done;
+            }
+            if breakpoints && self.breakpoints.contains(&self.vm.pc()) {
+                return Stop::Breakpoint;
+            }
+        }
+        Stop::Limit
+    }
+
+    fn report(&self, stop: Stop) {
+        match stop {
+            Stop::Done => {}
+            Stop::Limit => println!("still running after {RUN_LIMIT} steps"),
+            Stop::Breakpoint => println!("breakpoint"),
+            Stop::Halted => println!("halted after {} steps", self.vm.cycles()),
+            Stop::Fault(err) => println!("fault: {err}"),
+        }
+        if !self.vm.halt {
+            self.disassemble(self.vm.pc(), 1);
+        }
+    }
+
+    /// Runs one command, false when it's time to leave.
+    fn command(&mut self, line: &str) -> Result<bool> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            [] => {}
+            ["break" | "b", at] => {
+                let addr = self.address(at)?;
+                self.breakpoints.insert(addr);
+                println!("breakpoint at 0x{addr:04X}");
+            }
+            ["delete" | "d", at] => {
+                let addr = self.address(at)?;
+                if !self.breakpoints.remove(&addr) {
+                    println!("no breakpoint at 0x{addr:04X}");
+                }
+            }
+            ["breaks"] => {
+                for addr in &self.breakpoints {
+                    println!("0x{addr:04X}");
+                }
+            }
+            ["step" | "s", count @ ..] if count.len() <= 1 => {
+                let mut count = count.first().map_or(Ok(1), |count| number(count))?;
+                let stop = self.run(false, |_| {
+                    count = count.saturating_sub(1);
+                    count == 0
+                });
+                self.report(stop);
+            }
+            ["next" | "n"] => {
+                let pc = self.vm.pc();
+                let stop = match self.decode(pc) {
+                    // a call comes back to the next instruction with the stack as it was
+                    Some((Instruction::Call(_), size)) => {
+                        let (back, sp) = (pc.wrapping_add(size), self.vm.sp());
+                        self.run(true, |vm| vm.pc() == back && vm.sp() >= sp)
+                    }
+                    _ => self.run(false, |_| true),
+                };
+                self.report(stop);
+            }
+            ["continue" | "c"] => {
+                let stop = self.run(true, |_| false);
+                self.report(stop);
+            }
+            ["run"] => {
+                let stop = self.run(false, |_| false);
+                self.report(stop);
+            }
+            ["regs" | "r"] => print!("{}", self.vm.state()),
+            ["set", register, value] => {
+                let register = Registers::from_str_custom(register)
+                    .ok_or_else(|| anyhow::anyhow!("no register {register}"))?;
+                self.vm.set_register(register, number(value)?);
+                print!("{}", self.vm.state());
+            }
+            ["x", at, len @ ..] if len.len() <= 1 => {
+                let start = self.address(at)?;
+                let len = len.first().map_or(Ok(16), |len| number(len))?;
+                for row in (0..len).step_by(16) {
+                    let addr = start.wrapping_add(row);
+                    let bytes = (0..16.min(len - row))
+                        .map(|offset| match self.vm.bus.peek(addr.wrapping_add(offset)) {
+                            Some(byte) => format!("{byte:02X}"),
+                            None => "--".to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    println!("0x{addr:04X}: {bytes}");
+                }
+            }
+            ["poke", at, bytes @ ..] if !bytes.is_empty() => {
+                let start = self.address(at)?;
+                for (offset, byte) in bytes.iter().enumerate() {
+                    let byte = u8::try_from(number(byte)?)
+                        .map_err(|_| anyhow::anyhow!("not a byte: {byte}"))?;
+                    self.vm.bus.poke(start.wrapping_add(offset as u16), byte)?;
+                }
+            }
+            ["disas" | "l", rest @ ..] if rest.len() <= 2 => {
+                let count = rest.get(1).map_or(Ok(8), |count| number(count))?;
+                let start = match rest.first() {
+                    Some(at) => self.address(at)?,
+                    None => self.before(self.vm.pc(), 3),
+                };
+                self.disassemble(start, count as usize);
+            }
+            ["labels"] => {
+                let mut labels: Vec<_> = self.labels.iter().collect();
+                labels.sort_by_key(|(_, addr)| **addr);
+                for (label, addr) in labels {
+                    println!("0x{addr:04X} {label}");
+                }
+            }
+            ["help" | "h"] => println!("{HELP}"),
+            ["quit" | "q"] => return Ok(false),
+            _ => println!("what? try help"),
+        }
+        Ok(true)
+    }
+}
+
+fn main() -> Result<()> {
+    let path = env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("where's the program file you dumbass!\n{USAGE}"))?;
+    let path = Path::new(&path);
+    let file = fs::read(path)
+        .map_err(|_| anyhow::anyhow!("can't open the file, try giving a valid path."))?;
+    let (program, labels) = if path
+        .extension()
+        .is_some_and(|extension| extension == "naked")
+    {
+        let source = String::from_utf8(file)?;
+        (assemble(&source)?, labels(&source)?)
+    } else {
+        (file, HashMap::new())
+    };
+    if program.is_empty() {
+        return Err(anyhow::anyhow!("empty binary"));
+    }
+
+    // the console doesn't read stdin, the debugger does
+    let mut vm = Machine::standard(Console::new(io::empty(), io::stdout()))?;
+    vm.load_program(&program)?;
+    let mut debugger = Debugger {
+        vm,
+        labels,
+        breakpoints: BTreeSet::new(),
+    };
+    println!("{} bytes loaded, help for the commands", program.len());
+    debugger.report(Stop::Done);
+
+    let mut last = String::new();
+    let mut stdin = io::stdin().lock();
+    loop {
+        print!("(bmdbg) ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            line = last.clone();
+        }
+        match debugger.command(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => println!("error: {err}"),
+        }
+        last = line;
+    }
+    Ok(())
+}
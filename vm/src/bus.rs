@@ -0,0 +1,244 @@
+use crate::{
+    error::{Access, VmError},
+    irq::IrqController,
+    protection::Protection,
+};
+
+/// Whatever answers the memory accesses of the machine.
+///
+/// `Memory` is the default, embedders can plug in their own (sparse memory, memory that logs
+/// accesses, memory shared with the host, ...) with `Machine::with_bus`. An address the bus
+/// doesn't back should fail with `VmError::Bus`, leaving `pc` and `opcode` at 0.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> Result<u8, VmError>;
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), VmError>;
+
+    /// Reads `addr` without side effects, `None` if the bus can't do that.
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+}
+
+/// Something that lives at a range of addresses instead of plain memory.
+///
+/// Offsets are relative to the start of the range the device is mapped at, so a device
+/// doesn't care where it ends up in the address space.
+pub trait Device {
+    fn read(&mut self, offset: u16) -> u8;
+    fn write(&mut self, offset: u16, value: u8);
+
+    /// The state of the device for a snapshot, devices without state keep the default.
+    fn save(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Puts back the state `save` returned.
+    fn restore(&mut self, _state: &[u8]) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    /// Reads `offset` without side effects, `None` if the device can't do that.
+    fn peek(&self, _offset: u16) -> Option<u8> {
+        None
+    }
+
+    /// Called after every instruction, devices that keep time or raise interrupts do it here.
+    fn tick(&mut self, _irq: &mut IrqController) {}
+
+    /// A short description of the device state worth showing next to the registers.
+    fn status(&self) -> Option<String> {
+        None
+    }
+}
+
+struct Mapping {
+    start: u16,
+    end: u16,
+    device: Box<dyn Device>,
+}
+
+/// Routes every memory access of the machine, either to a mapped device or to the bus
+/// behind them, after checking it against the memory protection.
+pub struct SystemBus {
+    memory: Box<dyn Bus>,
+    devices: Vec<Mapping>,
+    pub protection: Protection,
+}
+
+impl SystemBus {
+    pub fn new(memory: impl Bus + 'static) -> Self {
+        Self {
+            memory: Box::new(memory),
+            devices: Vec::new(),
+            protection: Protection::new(),
+        }
+    }
+
+    /// Maps `device` at the `size` addresses starting at `start`, hiding the memory behind them.
+    pub fn map(&mut self, start: u16, size: u16, device: Box<dyn Device>) -> Result<(), VmError> {
+        let end = start
+            .checked_add(size.saturating_sub(1))
+            .filter(|_| size > 0)
+            .ok_or(VmError::Map { start, size })?;
+        if self
+            .devices
+            .iter()
+            .any(|mapping| start <= mapping.end && mapping.start <= end)
+        {
+            return Err(VmError::Map { start, size });
+        }
+        self.devices.push(Mapping { start, end, device });
+        Ok(())
+    }
+
+    /// Removes the device mapped at `start` and hands it back, the memory behind it shows again.
+    pub fn unmap(&mut self, start: u16) -> Option<Box<dyn Device>> {
+        let index = self
+            .devices
+            .iter()
+            .position(|mapping| mapping.start == start)?;
+        Some(self.devices.remove(index).device)
+    }
+
+    /// The bus behind the devices.
+    pub fn memory(&self) -> &dyn Bus {
+        self.memory.as_ref()
+    }
+
+    /// The bus behind the devices.
+    pub fn memory_mut(&mut self) -> &mut dyn Bus {
+        self.memory.as_mut()
+    }
+
+    /// Whether a device answers `addr`.
+    pub(crate) fn is_mapped(&self, addr: u16) -> bool {
+        self.mapping(addr).is_some()
+    }
+
+    fn mapping(&self, addr: u16) -> Option<&Mapping> {
+        self.devices
+            .iter()
+            .find(|mapping| mapping.start <= addr && addr <= mapping.end)
+    }
+
+    fn device(&mut self, addr: u16) -> Option<(&mut (dyn Device + 'static), u16)> {
+        self.devices
+            .iter_mut()
+            .find(|mapping| mapping.start <= addr && addr <= mapping.end)
+            .map(|mapping| (mapping.device.as_mut(), addr - mapping.start))
+    }
+
+    pub fn read(&mut self, addr: u16) -> Result<u8, VmError> {
+        self.protection.check(addr, Access::Read)?;
+        self.read_unchecked(addr)
+            .map_err(|err| access(err, Access::Read))
+    }
+
+    /// Reads a byte of an instruction.
+    pub fn fetch(&mut self, addr: u16) -> Result<u8, VmError> {
+        self.protection.check(addr, Access::Execute)?;
+        self.read_unchecked(addr)
+            .map_err(|err| access(err, Access::Execute))
+    }
+
+    /// Reads `addr` without side effects and without checking the protection, for the host
+    /// to look at memory. `None` if the device or bus behind it can't do that.
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        match self.mapping(addr) {
+            Some(mapping) => mapping.device.peek(addr - mapping.start),
+            None => self.memory.peek(addr),
+        }
+    }
+
+    fn read_unchecked(&mut self, addr: u16) -> Result<u8, VmError> {
+        match self.device(addr) {
+            Some((device, offset)) => Ok(device.read(offset)),
+            None => self.memory.read(addr),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) -> Result<(), VmError> {
+        self.protection.check(addr, Access::Write)?;
+        self.poke(addr, value)
+    }
+
+    /// Writes `addr` without checking the protection, for the host to patch memory (or code)
+    /// and for the journal to put back old values.
+    pub fn poke(&mut self, addr: u16, value: u8) -> Result<(), VmError> {
+        match self.device(addr) {
+            Some((device, offset)) => {
+                device.write(offset, value);
+                Ok(())
+            }
+            None => self.memory.write(addr, value),
+        }
+    }
+
+    /// The status of every device that has one, with the address it is mapped at.
+    pub fn status(&self) -> String {
+        self.devices
+            .iter()
+            .filter_map(|mapping| {
+                let status = mapping.device.status()?;
+                Some(format!("0x{:04X}: {}", mapping.start, status))
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    /// The saved state of every device with the address it is mapped at, in mapping order.
+    pub(crate) fn save_devices(&self) -> Vec<(u16, Vec<u8>)> {
+        self.devices
+            .iter()
+            .map(|mapping| (mapping.start, mapping.device.save()))
+            .collect()
+    }
+
+    /// Restores the states `save_devices` returned, the devices have to be mapped the same.
+    /// When a device refuses its state the ones restored before it are put back.
+    pub(crate) fn restore_devices(&mut self, states: &[(u16, &[u8])]) -> Result<(), VmError> {
+        let starts = self.devices.iter().map(|mapping| mapping.start);
+        if !starts.eq(states.iter().map(|(start, _)| *start)) {
+            return Err(VmError::Snapshot {
+                reason: "the devices aren't mapped like when the snapshot was taken".to_string(),
+            });
+        }
+        let saved = self.save_devices();
+        for (index, (_, state)) in states.iter().enumerate() {
+            if let Err(err) = self.devices[index].device.restore(state) {
+                for (mapping, (_, state)) in self.devices.iter_mut().zip(&saved).take(index) {
+                    let _ = mapping.device.restore(state);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn tick(&mut self, irq: &mut IrqController) {
+        for mapping in &mut self.devices {
+            mapping.device.tick(irq);
+        }
+    }
+
+    /// Copies `program` to address 0 on, going through the devices like any other write.
+    pub fn load(&mut self, program: &[u8]) -> Result<(), VmError> {
+        for (index, byte) in program.iter().enumerate() {
+            self.write(index as u16, *byte)?
+        }
+        Ok(())
+    }
+}
+
+/// A bus fault on a read is a fault of the kind of read the machine was making.
+fn access(err: VmError, access: Access) -> VmError {
+    match err {
+        VmError::Bus { addr, .. } => VmError::Bus {
+            pc: 0,
+            opcode: 0,
+            addr,
+            access,
+        },
+        err => err,
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{bus::Device, error::VmError};
+
+/// Where `main` maps the banked window, the bank-select port comes right after it.
+pub const BANK_BASE: u16 = 0x8000;
+pub const BANK_WINDOW: u16 = 0x4000;
+pub const BANK_COUNT: usize = 16;
+
+/// A backing store bigger than the address space, seen one bank at a time.
+///
+/// The device spans `window + 1` addresses: the first `window` show the selected bank and
+/// the last one is the bank-select port. Selecting a bank past the last one wraps around.
+#[derive(Debug, Clone)]
+pub struct BankedMemory {
+    bytes: Vec<u8>,
+    window: u16,
+    bank: u8,
+}
+
+impl BankedMemory {
+    /// `banks` banks of `window` bytes, all zero, with bank 0 selected.
+    ///
+    /// Panics if `banks` isn't between 1 and 256, or `window` between 1 and 0xFFFE (the
+    /// select port needs the address after it).
+    pub fn new(banks: usize, window: u16) -> Self {
+        assert!((1..=256).contains(&banks), "can't select {banks} banks");
+        assert!(
+            (1..u16::MAX).contains(&window),
+            "no room for a window of {window} bytes"
+        );
+        Self {
+            bytes: vec![0; banks * window as usize],
+            window,
+            bank: 0,
+        }
+    }
+
+    /// Addresses the device takes on the bus.
+    pub fn size(&self) -> u16 {
+        self.window + 1
+    }
+
+    pub fn banks(&self) -> usize {
+        self.bytes.len() / self.window as usize
+    }
+
+    pub fn bank(&self) -> u8 {
+        self.bank
+    }
+
+    /// The contents of `bank`, for filling it before the device is mapped.
+    pub fn bank_mut(&mut self, bank: u8) -> &mut [u8] {
+        let start = bank as usize % self.banks() * self.window as usize;
+        &mut self.bytes[start..start + self.window as usize]
+    }
+}
+
+impl Device for BankedMemory {
+    fn read(&mut self, offset: u16) -> u8 {
+        if offset == self.window {
+            return self.bank;
+        }
+        let bank = self.bank;
+        self.bank_mut(bank)[offset as usize]
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        if offset == self.window {
+            self.bank = (value as usize % self.banks()) as u8;
+            return;
+        }
+        let bank = self.bank;
+        self.bank_mut(bank)[offset as usize] = value;
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        if offset == self.window {
+            return Some(self.bank);
+        }
+        let start = self.bank as usize * self.window as usize;
+        self.bytes.get(start + offset as usize).copied()
+    }
+
+    /// The selected bank followed by every bank.
+    fn save(&self) -> Vec<u8> {
+        let mut state = vec![self.bank];
+        state.extend(&self.bytes);
+        state
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), VmError> {
+        match state.split_first() {
+            Some((&bank, bytes))
+                if bytes.len() == self.bytes.len() && (bank as usize) < self.banks() =>
+            {
+                self.bank = bank;
+                self.bytes.copy_from_slice(bytes);
+                Ok(())
+            }
+            _ => Err(VmError::Snapshot {
+                reason: "banked memory of a different size".to_string(),
+            }),
+        }
+    }
+
+    fn status(&self) -> Option<String> {
+        Some(format!("bank {}/{}", self.bank, self.banks()))
+    }
+}
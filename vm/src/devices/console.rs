@@ -0,0 +1,162 @@
+use std::{
+    cell::RefCell,
+    io::{self, Read, Write},
+    rc::Rc,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::{bus::Device, error::VmError};
+
+/// Where `main` maps the console.
+pub const CONSOLE_BASE: u16 = 0xE000;
+/// The console takes a data port and a status port.
+pub const CONSOLE_SIZE: u16 = 2;
+
+/// Offset of the data port: reading takes the next input byte (0 when there is none),
+/// writing sends a byte to the output.
+pub const CONSOLE_DATA: u16 = 0;
+/// Offset of the status port, bit 0 is set while there is input to read.
+pub const CONSOLE_STATUS: u16 = 1;
+pub const INPUT_AVAILABLE: u8 = 1 << 0;
+
+/// Where the console gets its input from.
+enum Input {
+    /// Read directly, for readers that never wait (a `Cursor`, a file).
+    Reader(Box<dyn Read>),
+    /// Read by a thread of its own, which sends the bytes over as they come.
+    Thread(Receiver<u8>),
+}
+
+/// A character device reading bytes from `input` and writing them to `output`.
+///
+/// Input is read one byte ahead to answer the status port, the status reads 0 once the
+/// input ran out. Output errors are dropped, a program has no way to handle them anyway.
+pub struct Console {
+    input: Input,
+    output: Box<dyn Write>,
+    next: Option<u8>,
+}
+
+impl Console {
+    /// A console reading `input` when the program asks, so `input` mustn't block.
+    pub fn new(input: impl Read + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            input: Input::Reader(Box::new(input)),
+            output: Box::new(output),
+            next: None,
+        }
+    }
+
+    /// A console reading `input` on a thread, so checking the status never waits: it reads
+    /// 0 until a byte has come in, and from then on until it has been read.
+    pub fn threaded(input: impl Read + Send + 'static, output: impl Write + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut input = input;
+            let mut buffer = [0; 256];
+            // stops at the end of the input, or when the console is gone
+            while let Ok(read @ 1..) = input.read(&mut buffer) {
+                if buffer[..read]
+                    .iter()
+                    .any(|byte| sender.send(*byte).is_err())
+                {
+                    break;
+                }
+            }
+        });
+        Self {
+            input: Input::Thread(receiver),
+            output: Box::new(output),
+            next: None,
+        }
+    }
+
+    /// A console on the host's stdin and stdout, stdin read on a thread.
+    pub fn stdio() -> Self {
+        Self::threaded(io::stdin(), io::stdout())
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        if self.next.is_none() {
+            self.next = match &mut self.input {
+                Input::Reader(input) => {
+                    let mut byte = [0];
+                    match input.read(&mut byte) {
+                        Ok(1) => Some(byte[0]),
+                        _ => None,
+                    }
+                }
+                Input::Thread(bytes) => bytes.try_recv().ok(),
+            };
+        }
+        self.next
+    }
+}
+
+impl Device for Console {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset {
+            CONSOLE_DATA => {
+                let byte = self.peek().unwrap_or(0);
+                self.next = None;
+                byte
+            }
+            CONSOLE_STATUS if self.peek().is_some() => INPUT_AVAILABLE,
+            _ => 0,
+        }
+    }
+
+    /// Only the byte read ahead, whatever is still unread stays with the input.
+    fn save(&self) -> Vec<u8> {
+        self.next.into_iter().collect()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), VmError> {
+        match *state {
+            [] => self.next = None,
+            [next] => self.next = Some(next),
+            _ => {
+                return Err(VmError::Snapshot {
+                    reason: "bad console state".to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        if offset == CONSOLE_DATA {
+            let _ = self
+                .output
+                .write_all(&[value])
+                .and_then(|_| self.output.flush());
+        }
+    }
+}
+
+/// In-memory output that stays readable after the console owning a clone of it was mapped.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+//! Devices ready to be mapped on the `SystemBus`.
+
+pub mod bank;
+pub mod console;
+pub mod timer;
+
+pub use bank::BankedMemory;
+pub use console::Console;
+pub use timer::Timer;
@@ -0,0 +1,112 @@
+use crate::{bus::Device, error::VmError, irq::IrqController};
+
+/// Where `main` maps the timer.
+pub const TIMER_BASE: u16 = 0xE008;
+pub const TIMER_SIZE: u16 = 5;
+
+/// Offsets of the timer ports. The reload value and the counter are 16 bits, low byte first.
+pub const TIMER_RELOAD: u16 = 0;
+pub const TIMER_CONTROL: u16 = 2;
+pub const TIMER_COUNTER: u16 = 3;
+
+/// Control bits: count down, and start over after firing instead of stopping.
+pub const TIMER_ENABLE: u8 = 1 << 0;
+pub const TIMER_PERIODIC: u8 = 1 << 1;
+
+/// A programmable interval timer counting executed instructions.
+///
+/// Writing the control port with `TIMER_ENABLE` loads the counter from the reload value,
+/// every instruction then counts it down by one and when it hits 0 the timer raises its IRQ
+/// line. A periodic timer reloads and keeps going, a one-shot one disables itself. A reload
+/// value of 0 never fires.
+#[derive(Debug, Clone)]
+pub struct Timer {
+    line: u8,
+    reload: u16,
+    control: u8,
+    counter: u16,
+}
+
+impl Timer {
+    /// A stopped timer raising `line` when it fires.
+    pub fn new(line: u8) -> Self {
+        Self {
+            line,
+            reload: 0,
+            control: 0,
+            counter: 0,
+        }
+    }
+
+    fn port(&self, offset: u16) -> u8 {
+        match offset {
+            TIMER_RELOAD => self.reload as u8,
+            1 => (self.reload >> 8) as u8,
+            TIMER_CONTROL => self.control,
+            TIMER_COUNTER => self.counter as u8,
+            4 => (self.counter >> 8) as u8,
+            _ => 0,
+        }
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.port(offset)
+    }
+
+    fn peek(&self, offset: u16) -> Option<u8> {
+        Some(self.port(offset))
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset {
+            TIMER_RELOAD => self.reload = self.reload & 0xFF00 | value as u16,
+            1 => self.reload = self.reload & 0x00FF | (value as u16) << 8,
+            TIMER_CONTROL => {
+                self.control = value & (TIMER_ENABLE | TIMER_PERIODIC);
+                self.counter = self.reload;
+            }
+            _ => {}
+        }
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let [reload_low, reload_high] = self.reload.to_le_bytes();
+        let [counter_low, counter_high] = self.counter.to_le_bytes();
+        vec![
+            reload_low,
+            reload_high,
+            self.control,
+            counter_low,
+            counter_high,
+        ]
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<(), VmError> {
+        let [reload_low, reload_high, control, counter_low, counter_high] = *state else {
+            return Err(VmError::Snapshot {
+                reason: "bad timer state".to_string(),
+            });
+        };
+        self.reload = u16::from_le_bytes([reload_low, reload_high]);
+        self.control = control;
+        self.counter = u16::from_le_bytes([counter_low, counter_high]);
+        Ok(())
+    }
+
+    fn tick(&mut self, irq: &mut IrqController) {
+        if self.control & TIMER_ENABLE == 0 || self.counter == 0 {
+            return;
+        }
+        self.counter -= 1;
+        if self.counter == 0 {
+            irq.raise(self.line);
+            if self.control & TIMER_PERIODIC != 0 {
+                self.counter = self.reload;
+            } else {
+                self.control &= !TIMER_ENABLE;
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt;
+
+/// The kind of memory access that caused a bus fault.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Which way the stack pointer ran off the stack.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StackFault {
+    Overflow,
+    Underflow,
+}
+
+/// Everything that can go wrong while running or assembling a byte machine program.
+///
+/// Faults raised by `Machine::step` carry the address of the faulting instruction (`pc`)
+/// and its first byte (`opcode`). `Memory` doesn't know which instruction is executing,
+/// so the faults it returns have both set to 0 until `step` fills them in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    /// Bad opcode, register code, ALU operation or jump condition.
+    Decode { pc: u16, opcode: u8 },
+    /// Access to an address that isn't backed by memory.
+    Bus {
+        pc: u16,
+        opcode: u8,
+        addr: u16,
+        access: Access,
+    },
+    /// Access the memory protection regions don't allow.
+    Protection {
+        pc: u16,
+        opcode: u8,
+        addr: u16,
+        access: Access,
+    },
+    /// Push on a full stack or pop from an empty one.
+    Stack {
+        pc: u16,
+        opcode: u8,
+        sp: u16,
+        fault: StackFault,
+    },
+    /// `Interrupt` with a signal nobody registered a handler for.
+    UnknownInterrupt { pc: u16, opcode: u8, signal: u8 },
+    /// Hardware interrupt on a line with neither a guest nor a host handler, `pc` is where
+    /// the machine was when it came in. The line is lowered, stepping again carries on.
+    UnhandledIrq { pc: u16, line: u8 },
+    /// Division with a zero divisor.
+    DivideByZero { pc: u16, opcode: u8 },
+    /// The instruction at `pc` hit a stopping watchpoint. Unlike the faults the instruction
+    /// has completed, PC already points at the next one.
+    Watchpoint {
+        pc: u16,
+        addr: u16,
+        access: Access,
+        old: Option<u8>,
+        new: u8,
+    },
+    /// `Machine::step_back` over the instruction at `pc`, which wrote `addr` in memory the
+    /// bus can't peek, so its old value is unknown.
+    Irreversible { pc: u16, addr: u16 },
+    /// `step` called on a machine that already halted.
+    Halted { pc: u16 },
+    /// Device mapped over an empty range, or over one that already has a device.
+    Map { start: u16, size: u16 },
+    /// Program of `size` bytes, more than the 0xFFFF a region can protect.
+    ProgramSize { size: usize },
+    /// Snapshot that can't be taken of this machine, or restored on it.
+    Snapshot { reason: String },
+    /// Instruction that has no encoding (unresolved label, operand out of range, ...).
+    Encode { reason: String },
+    /// Source line the assembler couldn't turn into an instruction.
+    Assemble { line: usize, reason: String },
+}
+
+impl VmError {
+    /// Stamps the faulting instruction onto errors raised below `Machine::step`.
+    pub(crate) fn at(self, pc: u16, opcode: u8) -> Self {
+        match self {
+            VmError::Decode { .. } => VmError::Decode { pc, opcode },
+            VmError::Bus { addr, access, .. } => VmError::Bus {
+                pc,
+                opcode,
+                addr,
+                access,
+            },
+            VmError::Protection { addr, access, .. } => VmError::Protection {
+                pc,
+                opcode,
+                addr,
+                access,
+            },
+            VmError::Stack { sp, fault, .. } => VmError::Stack {
+                pc,
+                opcode,
+                sp,
+                fault,
+            },
+            VmError::UnknownInterrupt { signal, .. } => {
+                VmError::UnknownInterrupt { pc, opcode, signal }
+            }
+            VmError::UnhandledIrq { line, .. } => VmError::UnhandledIrq { pc, line },
+            VmError::DivideByZero { .. } => VmError::DivideByZero { pc, opcode },
+            VmError::Watchpoint {
+                addr,
+                access,
+                old,
+                new,
+                ..
+            } => VmError::Watchpoint {
+                pc,
+                addr,
+                access,
+                old,
+                new,
+            },
+            VmError::Halted { .. } => VmError::Halted { pc },
+            err @ (VmError::Irreversible { .. }
+            | VmError::Map { .. }
+            | VmError::ProgramSize { .. }
+            | VmError::Snapshot { .. }
+            | VmError::Encode { .. }
+            | VmError::Assemble { .. }) => err,
+        }
+    }
+
+    /// Address of the instruction that faulted, if the error came from the machine.
+    pub fn pc(&self) -> Option<u16> {
+        match self {
+            VmError::Decode { pc, .. }
+            | VmError::Bus { pc, .. }
+            | VmError::Protection { pc, .. }
+            | VmError::Stack { pc, .. }
+            | VmError::UnknownInterrupt { pc, .. }
+            | VmError::UnhandledIrq { pc, .. }
+            | VmError::DivideByZero { pc, .. }
+            | VmError::Watchpoint { pc, .. }
+            | VmError::Irreversible { pc, .. }
+            | VmError::Halted { pc } => Some(*pc),
+            VmError::Map { .. }
+            | VmError::ProgramSize { .. }
+            | VmError::Snapshot { .. }
+            | VmError::Encode { .. }
+            | VmError::Assemble { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::Decode { pc, opcode } => {
+                write!(f, "can't decode opcode 0x{:02X} @ 0x{:04X}", opcode, pc)
+            }
+            VmError::Bus {
+                pc,
+                opcode,
+                addr,
+                access,
+            } => write!(
+                f,
+                "bus fault: {:?} of 0x{:04X} by opcode 0x{:02X} @ 0x{:04X}",
+                access, addr, opcode, pc
+            ),
+            VmError::Protection {
+                pc,
+                opcode,
+                addr,
+                access,
+            } => write!(
+                f,
+                "protection fault: {:?} of 0x{:04X} by opcode 0x{:02X} @ 0x{:04X}",
+                access, addr, opcode, pc
+            ),
+            VmError::Stack {
+                pc,
+                opcode,
+                sp,
+                fault,
+            } => write!(
+                f,
+                "stack {:?} with SP = 0x{:04X} by opcode 0x{:02X} @ 0x{:04X}",
+                fault, sp, opcode, pc
+            ),
+            VmError::UnknownInterrupt { pc, signal, .. } => write!(
+                f,
+                "0x{:X} is not a valid signal, dumbass! (@ 0x{:04X})",
+                signal, pc
+            ),
+            VmError::UnhandledIrq { pc, line } => {
+                write!(f, "nothing handles IRQ line {} (@ 0x{:04X})", line, pc)
+            }
+            VmError::DivideByZero { pc, opcode } => write!(
+                f,
+                "division by zero by opcode 0x{:02X} @ 0x{:04X}",
+                opcode, pc
+            ),
+            VmError::Watchpoint {
+                pc,
+                addr,
+                access,
+                old,
+                new,
+            } => {
+                write!(f, "watchpoint: {:?} of 0x{:04X}", access, addr)?;
+                match old {
+                    Some(old) if old != new => write!(f, " (0x{:02X} -> 0x{:02X})", old, new)?,
+                    _ => write!(f, " (0x{:02X})", new)?,
+                }
+                write!(f, " @ 0x{:04X}", pc)
+            }
+            VmError::Irreversible { pc, addr } => write!(
+                f,
+                "can't step back over 0x{:04X}, the old value of 0x{:04X} is unknown",
+                pc, addr
+            ),
+            VmError::Halted { pc } => write!(f, "machine halted @ 0x{:04X}", pc),
+            VmError::Map { start, size } => write!(
+                f,
+                "can't map a device of {} bytes at 0x{:04X}, the range is empty or taken",
+                size, start
+            ),
+            VmError::ProgramSize { size } => {
+                write!(f, "a program of {} bytes is too big, 0xFFFF at most", size)
+            }
+            VmError::Snapshot { reason } => write!(f, "snapshot: {}", reason),
+            VmError::Encode { reason } => write!(f, "can't encode: {}", reason),
+            VmError::Assemble { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
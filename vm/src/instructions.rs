@@ -1,36 +1,545 @@
-use crate::Registers;
+use std::fmt;
 
-#[derive(Debug)]
+use crate::{Registers, VmError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
     Nop,                                        // 0000 0000
-    Push(u8),                                   // 0001 xxxx | iiiiiiii
+    Push(u8),                                   // 0001 0000 | iiiiiiii
     PopRegister(Registers),                     // 0010 rrrr
     PushRegister(Registers),                    // 0011 rrrr
     AddStack,                                   // 0100 0000
-    LoadImmediate(Registers, u8),               // 0101 rrrr | iiiiiiii
+    LoadImmediate(Registers, u16),              // 0101 rrrr | iiiiiiii [| iiiiiiii]
     LoadMemory(Registers, u16),                 // 0110 rrrr | aaaaaaaa | aaaaaaaa
     Store(Registers, u16),                      // 0111 rrrr | aaaaaaaa | aaaaaaaa
+    LoadIndirect(Registers, Address),           // 0110 1rrr | mmhhhlll [| oooooooo]
+    StoreIndirect(Registers, Address),          // 0111 1rrr | mmhhhlll [| oooooooo]
     ALU(ALUOperation, Registers, Registers),    // 1000 oooo | rrrr | rrrr
+    ALUImmediate(ALUOperation, Registers, u8),  // 1000 oooo | rrrr 1111 | iiiiiiii
     Jump(JumpTarget),                           // 1001 0000 | aaaaaaaa | aaaaaaaa
     JumpConditional(JumpCondition, JumpTarget), // 1010 cccc | aaaaaaaa | aaaaaaaa
+    Call(JumpTarget),                           // 1011 0000 | aaaaaaaa | aaaaaaaa
+    Ret,                                        // 1100 0000
+    Leave,                                      // 1100 0001
+    Enter(u8),                                  // 1101 0000 | nnnnnnnn
+    Halt,                                       // 1110 0000 | 00000000
+    Move(Registers, Registers),                 // 1110 0000 | 00000001 | rrrr | rrrr
+    Iret,                                       // 1110 0000 | 00000010
+    Ei,                                         // 1110 0000 | 00000011
+    Di,                                         // 1110 0000 | 00000100
     Interrupt(u8),                              // 1111 iiii
 }
 
+/// Opcode prefixing the extended instructions, the byte after it selects the instruction.
+pub const EXTENDED: u8 = 0xE0;
+
+/// Bytes in the longest encoding, an opcode and two operand bytes.
+pub const MAX_INSTRUCTION_SIZE: u16 = 3;
+
+/// Second register code of an ALU instruction that means "an immediate byte follows".
+pub const ALU_IMMEDIATE: u8 = 0xF;
+
+/// Placeholder decode fault, `Machine::step` stamps the real PC and opcode on it.
+pub(crate) const DECODE_FAULT: VmError = VmError::Decode { pc: 0, opcode: 0 };
+
+/// How the operands of an instruction are packed around its opcode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Layout {
+    Implied,          // nothing
+    Byte,             // iiiiiiii
+    Register,         // rrrr in the opcode byte
+    RegisterValue,    // rrrr | iiiiiiii, two bytes for 16-bit registers
+    RegisterAddress,  // 0rrr | aaaaaaaa | aaaaaaaa
+    RegisterIndirect, // 1rrr | mmhhhlll [| oooooooo]
+    Alu,              // oooo | rrrr rrrr, or oooo | rrrr 1111 | iiiiiiii
+    Address,          // aaaaaaaa | aaaaaaaa
+    ConditionAddress, // cccc | aaaaaaaa | aaaaaaaa
+    Signal,           // iiii in the opcode byte
+    Registers,        // rrrr rrrr
+}
+
+/// One row of the instruction set table.
 #[derive(Debug)]
+pub struct Encoding {
+    pub mnemonic: &'static str,
+    /// First byte with every operand bit cleared.
+    pub opcode: u8,
+    /// The bits of the first byte that belong to the opcode.
+    pub mask: u8,
+    /// Second opcode byte of the instructions behind the `EXTENDED` prefix.
+    pub extended: Option<u8>,
+    pub layout: Layout,
+}
+
+const fn row(mnemonic: &'static str, opcode: u8, mask: u8, layout: Layout) -> Encoding {
+    Encoding {
+        mnemonic,
+        opcode,
+        mask,
+        extended: None,
+        layout,
+    }
+}
+
+const fn extended(mnemonic: &'static str, opcode: u8, layout: Layout) -> Encoding {
+    Encoding {
+        mnemonic,
+        opcode: EXTENDED,
+        mask: 0xFF,
+        extended: Some(opcode),
+        layout,
+    }
+}
+
+/// The instruction set, both `Instruction::encode` and `Instruction::decode` are driven by it.
+pub const ISA: [Encoding; 24] = [
+    row("Nop", 0x00, 0xFF, Layout::Implied),
+    row("Push", 0x10, 0xFF, Layout::Byte),
+    row("PopRegister", 0x20, 0xF0, Layout::Register),
+    row("PushRegister", 0x30, 0xF0, Layout::Register),
+    row("AddStack", 0x40, 0xFF, Layout::Implied),
+    row("LoadImmediate", 0x50, 0xF0, Layout::RegisterValue),
+    row("LoadMemory", 0x60, 0xF8, Layout::RegisterAddress),
+    row("LoadIndirect", 0x68, 0xF8, Layout::RegisterIndirect),
+    row("Store", 0x70, 0xF8, Layout::RegisterAddress),
+    row("StoreIndirect", 0x78, 0xF8, Layout::RegisterIndirect),
+    row("ALU", 0x80, 0xF0, Layout::Alu),
+    row("Jump", 0x90, 0xFF, Layout::Address),
+    row("JumpConditional", 0xA0, 0xF0, Layout::ConditionAddress),
+    row("Call", 0xB0, 0xFF, Layout::Address),
+    row("Ret", 0xC0, 0xFF, Layout::Implied),
+    row("Leave", 0xC1, 0xFF, Layout::Implied),
+    row("Enter", 0xD0, 0xFF, Layout::Byte),
+    extended("Halt", 0x00, Layout::Implied),
+    extended("Move", 0x01, Layout::Registers),
+    extended("Iret", 0x02, Layout::Implied),
+    extended("Ei", 0x03, Layout::Implied),
+    extended("Di", 0x04, Layout::Implied),
+    row("Interrupt", 0xF0, 0xF0, Layout::Signal),
+    // ALU with an immediate shares the ALU opcode, the Alu layout tells them apart
+    row("ALUImmediate", 0x80, 0xF0, Layout::Alu),
+];
+
+/// The operands of an instruction, in the shape its `Layout` packs them.
+enum Operands {
+    Implied,
+    Byte(u8),
+    Register(Registers),
+    RegisterValue(Registers, u16),
+    RegisterAddress(Registers, u16),
+    RegisterIndirect(Registers, Address),
+    Alu(ALUOperation, Registers, Registers),
+    AluImmediate(ALUOperation, Registers, u8),
+    Address(u16),
+    ConditionAddress(JumpCondition, u16),
+    Signal(u8),
+    Registers(Registers, Registers),
+}
+
+fn encode_error(reason: String) -> VmError {
+    VmError::Encode { reason }
+}
+
+impl Layout {
+    fn read(
+        self,
+        opcode: u8,
+        next: &mut impl FnMut() -> Result<u8, VmError>,
+    ) -> Result<Operands, VmError> {
+        let low = opcode & 0x0F;
+        let register = |code: u8| Registers::from_u8_custom(code).ok_or(DECODE_FAULT);
+        let mut address = || -> Result<u16, VmError> { Ok((next()? as u16) << 8 | next()? as u16) };
+
+        Ok(match self {
+            Layout::Implied => Operands::Implied,
+            Layout::Byte => Operands::Byte(next()?),
+            Layout::Register => Operands::Register(register(low)?),
+            Layout::RegisterValue => {
+                let reg = register(low)?;
+                let value = if reg.is_wide() {
+                    (next()? as u16) << 8 | next()? as u16
+                } else {
+                    next()? as u16
+                };
+                Operands::RegisterValue(reg, value)
+            }
+            Layout::RegisterAddress => Operands::RegisterAddress(register(low)?, address()?),
+            Layout::RegisterIndirect => {
+                let reg = register(low & 0x7)?;
+                let mode = next()?;
+                let address = match Address::from_u8_custom(mode) {
+                    Some(address) => address,
+                    None => Address::with_offset(mode, next()?).ok_or(DECODE_FAULT)?,
+                };
+                Operands::RegisterIndirect(reg, address)
+            }
+            Layout::Alu => {
+                let operation = ALUOperation::from_u8_custom(low).ok_or(DECODE_FAULT)?;
+                let registers = next()?;
+                let reg1 = register(registers >> 4)?;
+                if registers & 0xF == ALU_IMMEDIATE {
+                    Operands::AluImmediate(operation, reg1, next()?)
+                } else {
+                    Operands::Alu(operation, reg1, register(registers & 0xF)?)
+                }
+            }
+            Layout::Address => Operands::Address(address()?),
+            Layout::ConditionAddress => {
+                let condition = JumpCondition::from_u8_custom(low).ok_or(DECODE_FAULT)?;
+                Operands::ConditionAddress(condition, address()?)
+            }
+            Layout::Signal => Operands::Signal(low),
+            Layout::Registers => {
+                let registers = next()?;
+                Operands::Registers(register(registers >> 4)?, register(registers & 0xF)?)
+            }
+        })
+    }
+}
+
+impl Operands {
+    /// Appends the operands to `bytes`, whose first byte is the bare opcode.
+    fn write(self, bytes: &mut Vec<u8>) -> Result<(), VmError> {
+        // registers sharing a byte with the indirect bit or an addressing mode only get 3 bits
+        let narrow = |reg: Registers| {
+            if (reg as u8) < 8 {
+                Ok(reg as u8)
+            } else {
+                Err(encode_error(format!(
+                    "{:?} can't be used to address memory",
+                    reg
+                )))
+            }
+        };
+        let word =
+            |bytes: &mut Vec<u8>, value: u16| bytes.extend([(value >> 8) as u8, value as u8]);
+
+        match self {
+            Operands::Implied => {}
+            Operands::Byte(value) => bytes.push(value),
+            Operands::Register(reg) => bytes[0] |= reg as u8,
+            Operands::RegisterValue(reg, value) => {
+                bytes[0] |= reg as u8;
+                if reg.is_wide() {
+                    word(bytes, value);
+                } else if value > 0xFF {
+                    return Err(encode_error(format!("{:?} can't hold {}", reg, value)));
+                } else {
+                    bytes.push(value as u8);
+                }
+            }
+            Operands::RegisterAddress(reg, address) => {
+                bytes[0] |= narrow(reg)?;
+                word(bytes, address);
+            }
+            Operands::RegisterIndirect(reg, address) => {
+                bytes[0] |= narrow(reg)?;
+                match address {
+                    Address::Pair(high, low) | Address::PairPostIncrement(high, low) => {
+                        narrow(high)?;
+                        narrow(low)?;
+                    }
+                    Address::Offset(base, _) | Address::PostIncrement(base) => {
+                        narrow(base)?;
+                    }
+                }
+                bytes.push(address.mode());
+                if let Address::Offset(_, offset) = address {
+                    bytes.push(offset as u8);
+                }
+            }
+            Operands::Alu(operation, reg1, reg2) => {
+                bytes[0] |= operation as u8;
+                bytes.push((reg1 as u8) << 4 | reg2 as u8);
+            }
+            Operands::AluImmediate(operation, reg, value) => {
+                bytes[0] |= operation as u8;
+                bytes.extend([(reg as u8) << 4 | ALU_IMMEDIATE, value]);
+            }
+            Operands::Address(address) => word(bytes, address),
+            Operands::ConditionAddress(condition, address) => {
+                bytes[0] |= condition as u8;
+                word(bytes, address);
+            }
+            Operands::Signal(signal) => {
+                if signal > 0xF {
+                    return Err(encode_error(format!(
+                        "interrupt {} doesn't fit in 4 bits",
+                        signal
+                    )));
+                }
+                bytes[0] |= signal;
+            }
+            Operands::Registers(reg1, reg2) => bytes.push((reg1 as u8) << 4 | reg2 as u8),
+        }
+        Ok(())
+    }
+}
+
+impl Instruction {
+    /// Decodes the instruction starting with `opcode`, pulling any further bytes from `next`.
+    pub fn decode_with(
+        opcode: u8,
+        mut next: impl FnMut() -> Result<u8, VmError>,
+    ) -> Result<Self, VmError> {
+        let extended = if opcode == EXTENDED {
+            Some(next()?)
+        } else {
+            None
+        };
+        let encoding = ISA
+            .iter()
+            .find(|encoding| {
+                opcode & encoding.mask == encoding.opcode && encoding.extended == extended
+            })
+            .ok_or(DECODE_FAULT)?;
+
+        Ok(
+            match (encoding.mnemonic, encoding.layout.read(opcode, &mut next)?) {
+                (_, Operands::Alu(operation, reg1, reg2)) => {
+                    Instruction::ALU(operation, reg1, reg2)
+                }
+                (_, Operands::AluImmediate(operation, reg, value)) => {
+                    Instruction::ALUImmediate(operation, reg, value)
+                }
+                ("Push", Operands::Byte(value)) => Instruction::Push(value),
+                ("Enter", Operands::Byte(size)) => Instruction::Enter(size),
+                ("PopRegister", Operands::Register(reg)) => Instruction::PopRegister(reg),
+                ("PushRegister", Operands::Register(reg)) => Instruction::PushRegister(reg),
+                ("LoadImmediate", Operands::RegisterValue(reg, value)) => {
+                    Instruction::LoadImmediate(reg, value)
+                }
+                ("LoadMemory", Operands::RegisterAddress(reg, address)) => {
+                    Instruction::LoadMemory(reg, address)
+                }
+                ("Store", Operands::RegisterAddress(reg, address)) => {
+                    Instruction::Store(reg, address)
+                }
+                ("LoadIndirect", Operands::RegisterIndirect(reg, address)) => {
+                    Instruction::LoadIndirect(reg, address)
+                }
+                ("StoreIndirect", Operands::RegisterIndirect(reg, address)) => {
+                    Instruction::StoreIndirect(reg, address)
+                }
+                ("Jump", Operands::Address(address)) => {
+                    Instruction::Jump(JumpTarget::Address(address))
+                }
+                ("Call", Operands::Address(address)) => {
+                    Instruction::Call(JumpTarget::Address(address))
+                }
+                ("JumpConditional", Operands::ConditionAddress(condition, address)) => {
+                    Instruction::JumpConditional(condition, JumpTarget::Address(address))
+                }
+                ("Move", Operands::Registers(dst, src)) => Instruction::Move(dst, src),
+                ("Interrupt", Operands::Signal(signal)) => Instruction::Interrupt(signal),
+                ("Nop", _) => Instruction::Nop,
+                ("AddStack", _) => Instruction::AddStack,
+                ("Ret", _) => Instruction::Ret,
+                ("Leave", _) => Instruction::Leave,
+                ("Halt", _) => Instruction::Halt,
+                ("Iret", _) => Instruction::Iret,
+                ("Ei", _) => Instruction::Ei,
+                ("Di", _) => Instruction::Di,
+                _ => unreachable!("{} can't be built from its own layout", encoding.mnemonic),
+            },
+        )
+    }
+
+    /// Decodes the instruction at the start of `bytes`, returning it and its size.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, usize), VmError> {
+        let mut rest = bytes.iter();
+        let opcode = *rest.next().ok_or(DECODE_FAULT)?;
+        let decode_fault = VmError::Decode { pc: 0, opcode };
+        let instruction =
+            Self::decode_with(opcode, || rest.next().copied().ok_or(decode_fault.clone()))
+                .map_err(|_| decode_fault.clone())?;
+        Ok((instruction, bytes.len() - rest.len()))
+    }
+
+    /// Encodes the instruction, jump targets have to be resolved to addresses by now.
+    pub fn encode(&self) -> Result<Vec<u8>, VmError> {
+        self.encode_targets(true)
+    }
+
+    /// Number of bytes the instruction encodes to, unresolved labels count as addresses.
+    pub fn size(&self) -> u16 {
+        self.encode_targets(false)
+            .map_or(0, |bytes| bytes.len() as u16)
+    }
+
+    /// The row of `ISA` this instruction is encoded with.
+    pub fn encoding(&self) -> &'static Encoding {
+        let mnemonic = match self {
+            Instruction::Nop => "Nop",
+            Instruction::Push(_) => "Push",
+            Instruction::PopRegister(_) => "PopRegister",
+            Instruction::PushRegister(_) => "PushRegister",
+            Instruction::AddStack => "AddStack",
+            Instruction::LoadImmediate(..) => "LoadImmediate",
+            Instruction::LoadMemory(..) => "LoadMemory",
+            Instruction::Store(..) => "Store",
+            Instruction::LoadIndirect(..) => "LoadIndirect",
+            Instruction::StoreIndirect(..) => "StoreIndirect",
+            Instruction::ALU(..) => "ALU",
+            Instruction::ALUImmediate(..) => "ALUImmediate",
+            Instruction::Jump(_) => "Jump",
+            Instruction::JumpConditional(..) => "JumpConditional",
+            Instruction::Call(_) => "Call",
+            Instruction::Ret => "Ret",
+            Instruction::Leave => "Leave",
+            Instruction::Enter(_) => "Enter",
+            Instruction::Halt => "Halt",
+            Instruction::Move(..) => "Move",
+            Instruction::Iret => "Iret",
+            Instruction::Ei => "Ei",
+            Instruction::Di => "Di",
+            Instruction::Interrupt(_) => "Interrupt",
+        };
+        ISA.iter()
+            .find(|encoding| encoding.mnemonic == mnemonic)
+            .expect("every instruction has a row in the ISA table")
+    }
+
+    fn encode_targets(&self, resolved: bool) -> Result<Vec<u8>, VmError> {
+        let target = |target: &JumpTarget| match target {
+            JumpTarget::Address(address) => Ok(*address),
+            JumpTarget::Label(_) if !resolved => Ok(0),
+            JumpTarget::Label(label) => Err(encode_error(format!(
+                "Unresolved label in {} instruction = {label}",
+                self.encoding().mnemonic
+            ))),
+        };
+        let operands = match self {
+            Instruction::Nop
+            | Instruction::AddStack
+            | Instruction::Ret
+            | Instruction::Leave
+            | Instruction::Halt
+            | Instruction::Iret
+            | Instruction::Ei
+            | Instruction::Di => Operands::Implied,
+            Instruction::Push(value) | Instruction::Enter(value) => Operands::Byte(*value),
+            Instruction::PopRegister(reg) | Instruction::PushRegister(reg) => {
+                Operands::Register(*reg)
+            }
+            Instruction::LoadImmediate(reg, value) => Operands::RegisterValue(*reg, *value),
+            Instruction::LoadMemory(reg, address) | Instruction::Store(reg, address) => {
+                Operands::RegisterAddress(*reg, *address)
+            }
+            Instruction::LoadIndirect(reg, address) | Instruction::StoreIndirect(reg, address) => {
+                Operands::RegisterIndirect(*reg, *address)
+            }
+            Instruction::ALU(operation, reg1, reg2) => Operands::Alu(*operation, *reg1, *reg2),
+            Instruction::ALUImmediate(operation, reg, value) => {
+                Operands::AluImmediate(*operation, *reg, *value)
+            }
+            Instruction::Jump(address) | Instruction::Call(address) => {
+                Operands::Address(target(address)?)
+            }
+            Instruction::JumpConditional(condition, address) => {
+                Operands::ConditionAddress(*condition, target(address)?)
+            }
+            Instruction::Move(dst, src) => Operands::Registers(*dst, *src),
+            Instruction::Interrupt(signal) => Operands::Signal(*signal),
+        };
+
+        let encoding = self.encoding();
+        let mut bytes = vec![encoding.opcode];
+        bytes.extend(encoding.extended);
+        operands.write(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// The instruction in assembler syntax, addresses in hex. The assembler reads it back as the
+/// same instruction.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Push(value) => write!(f, "Push {value}"),
+            Instruction::PopRegister(reg) => write!(f, "PopRegister {reg}"),
+            Instruction::PushRegister(reg) => write!(f, "PushRegister {reg}"),
+            Instruction::LoadImmediate(reg, value) if reg.is_wide() => {
+                write!(f, "LoadImmediate {reg} 0x{value:04X}")
+            }
+            Instruction::LoadImmediate(reg, value) => write!(f, "LoadImmediate {reg} {value}"),
+            Instruction::LoadMemory(reg, addr) => write!(f, "LoadMemory {reg} 0x{addr:04X}"),
+            Instruction::Store(reg, addr) => write!(f, "Store {reg} 0x{addr:04X}"),
+            Instruction::LoadIndirect(reg, address) => write!(f, "LoadMemory {reg} {address}"),
+            Instruction::StoreIndirect(reg, address) => write!(f, "Store {reg} {address}"),
+            Instruction::ALU(operation, reg1, reg2) => write!(f, "ALU {operation:?} {reg1} {reg2}"),
+            Instruction::ALUImmediate(operation, reg, value) => {
+                write!(f, "{operation:?}I {reg} {value}")
+            }
+            Instruction::Jump(target) => write!(f, "Jump {target}"),
+            Instruction::JumpConditional(condition, target) => {
+                write!(f, "JumpConditional {condition:?} {target}")
+            }
+            Instruction::Call(target) => write!(f, "Call {target}"),
+            Instruction::Enter(size) => write!(f, "Enter {size}"),
+            Instruction::Move(dst, src) => write!(f, "Move {dst} {src}"),
+            Instruction::Interrupt(signal) => write!(f, "Interrupt {signal}"),
+            Instruction::Nop
+            | Instruction::AddStack
+            | Instruction::Ret
+            | Instruction::Leave
+            | Instruction::Halt
+            | Instruction::Iret
+            | Instruction::Ei
+            | Instruction::Di => write!(f, "{self:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum JumpTarget {
     Address(u16),
     Label(String),
 }
 
-#[derive(Debug, Copy, Clone)]
+impl fmt::Display for JumpTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JumpTarget::Address(addr) => write!(f, "0x{addr:04X}"),
+            JumpTarget::Label(label) => write!(f, "{label}"),
+        }
+    }
+}
+
+/// Where `LoadIndirect`/`StoreIndirect` find their address.
+///
+/// Encoded as a mode byte `mmhhhlll`, the offset form is followed by the offset byte.
+/// Post-increment forms bump the address by the number of bytes moved after the access.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Address {
+    Pair(Registers, Registers),              // 00hh hlll            [H:L]
+    PairPostIncrement(Registers, Registers), // 01hh hlll            [H:L]+
+    Offset(Registers, i8),                   // 10rr r000 | oooooooo [R+o]
+    PostIncrement(Registers),                // 11rr r000            [R]+
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ALUOperation {
     Add, // 0000
     Sub, // 0001
     Mul, // 0010
     Div, // 0011
+    And, // 0100
+    Or,  // 0101
+    Xor, // 0110
+    Not, // 0111    ignores the second register
+    Shl, // 1000    shifts by the second register
+    Shr, // 1001
+    Rcl, // 1010    rotate left through Carry
+    Rcr, // 1011    rotate right through Carry
+    Mod, // 1100
+    Cmp, // 1101    Sub that only sets the flags
+    Adc, // 1110    Add plus Carry
+    Sbc, // 1111    Sub minus Carry (borrow)
 }
 
-#[derive(Debug, Copy, Clone)]
+/// `LT`/`GT`/`GE`/`LE` compare unsigned, the `S` variants compare signed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum JumpCondition {
     LT,  // 0000
     GT,  // 0001
@@ -38,6 +547,10 @@ pub enum JumpCondition {
     NEQ, // 0011
     GE,  // 0100
     LE,  // 0101
+    SLT, // 0110
+    SGT, // 0111
+    SGE, // 1000
+    SLE, // 1001
 }
 
 impl JumpCondition {
@@ -49,6 +562,10 @@ impl JumpCondition {
             3 => Some(JumpCondition::NEQ),
             4 => Some(JumpCondition::GE),
             5 => Some(JumpCondition::LE),
+            6 => Some(JumpCondition::SLT),
+            7 => Some(JumpCondition::SGT),
+            8 => Some(JumpCondition::SGE),
+            9 => Some(JumpCondition::SLE),
             _ => None,
         }
     }
@@ -61,6 +578,10 @@ impl JumpCondition {
             "NEQ" => Some(JumpCondition::NEQ),
             "GE" => Some(JumpCondition::GE),
             "LE" => Some(JumpCondition::LE),
+            "SLT" => Some(JumpCondition::SLT),
+            "SGT" => Some(JumpCondition::SGT),
+            "SGE" => Some(JumpCondition::SGE),
+            "SLE" => Some(JumpCondition::SLE),
             _ => None,
         }
     }
@@ -73,6 +594,18 @@ impl ALUOperation {
             1 => Some(ALUOperation::Sub),
             2 => Some(ALUOperation::Mul),
             3 => Some(ALUOperation::Div),
+            4 => Some(ALUOperation::And),
+            5 => Some(ALUOperation::Or),
+            6 => Some(ALUOperation::Xor),
+            7 => Some(ALUOperation::Not),
+            8 => Some(ALUOperation::Shl),
+            9 => Some(ALUOperation::Shr),
+            10 => Some(ALUOperation::Rcl),
+            11 => Some(ALUOperation::Rcr),
+            12 => Some(ALUOperation::Mod),
+            13 => Some(ALUOperation::Cmp),
+            14 => Some(ALUOperation::Adc),
+            15 => Some(ALUOperation::Sbc),
             _ => None,
         }
     }
@@ -83,7 +616,109 @@ impl ALUOperation {
             "Sub" => Some(ALUOperation::Sub),
             "Mul" => Some(ALUOperation::Mul),
             "Div" => Some(ALUOperation::Div),
+            "And" => Some(ALUOperation::And),
+            "Or" => Some(ALUOperation::Or),
+            "Xor" => Some(ALUOperation::Xor),
+            "Not" => Some(ALUOperation::Not),
+            "Shl" => Some(ALUOperation::Shl),
+            "Shr" => Some(ALUOperation::Shr),
+            "Rcl" => Some(ALUOperation::Rcl),
+            "Rcr" => Some(ALUOperation::Rcr),
+            "Mod" => Some(ALUOperation::Mod),
+            "Cmp" => Some(ALUOperation::Cmp),
+            "Adc" => Some(ALUOperation::Adc),
+            "Sbc" => Some(ALUOperation::Sbc),
+            _ => None,
+        }
+    }
+}
+
+/// The syntax `Address::from_str_custom` parses.
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Pair(high, low) => write!(f, "[{high}:{low}]"),
+            Address::PairPostIncrement(high, low) => write!(f, "[{high}:{low}]+"),
+            Address::Offset(base, 0) => write!(f, "[{base}]"),
+            Address::Offset(base, offset) => write!(f, "[{base}{offset:+}]"),
+            Address::PostIncrement(base) => write!(f, "[{base}]+"),
+        }
+    }
+}
+
+impl Address {
+    /// Parses `[H:L]`, `[H:L]+`, `[R]`, `[R+o]`, `[R-o]` and `[R]+`.
+    pub fn from_str_custom(value: &str) -> Option<Self> {
+        let (inner, post_increment) = match value.strip_suffix('+') {
+            Some(inner) => (inner, true),
+            None => (value, false),
+        };
+        let inner = inner.strip_prefix('[')?.strip_suffix(']')?;
+
+        if let Some((high, low)) = inner.split_once(':') {
+            let high = Registers::from_str_custom(high)?;
+            let low = Registers::from_str_custom(low)?;
+            return Some(if post_increment {
+                Address::PairPostIncrement(high, low)
+            } else {
+                Address::Pair(high, low)
+            });
+        }
+
+        if post_increment {
+            return Some(Address::PostIncrement(Registers::from_str_custom(inner)?));
+        }
+
+        match inner.find(['+', '-']) {
+            Some(index) => {
+                let offset = inner[index..].strip_prefix('+').unwrap_or(&inner[index..]);
+                Some(Address::Offset(
+                    Registers::from_str_custom(&inner[..index])?,
+                    offset.parse::<i8>().ok()?,
+                ))
+            }
+            None => Some(Address::Offset(Registers::from_str_custom(inner)?, 0)),
+        }
+    }
+
+    /// Builds the address from its mode byte, `None` for the offset form since that needs
+    /// the offset byte as well, see `Address::with_offset`.
+    pub fn from_u8_custom(value: u8) -> Option<Self> {
+        let high = Registers::from_u8_custom((value >> 3) & 0x7)?;
+        let low = Registers::from_u8_custom(value & 0x7)?;
+        match value >> 6 {
+            0 => Some(Address::Pair(high, low)),
+            1 => Some(Address::PairPostIncrement(high, low)),
+            3 if value & 0x7 == 0 => Some(Address::PostIncrement(high)),
             _ => None,
         }
     }
+
+    /// The offset form of a mode byte, `None` if the mode byte isn't one.
+    pub fn with_offset(value: u8, offset: u8) -> Option<Self> {
+        if value >> 6 != 2 || value & 0x7 != 0 {
+            return None;
+        }
+        let base = Registers::from_u8_custom((value >> 3) & 0x7)?;
+        Some(Address::Offset(base, offset as i8))
+    }
+
+    /// Whether `reg` is the base register or one half of the base pair.
+    pub fn uses(&self, reg: Registers) -> bool {
+        match *self {
+            Address::Pair(high, low) | Address::PairPostIncrement(high, low) => {
+                reg == high || reg == low
+            }
+            Address::Offset(base, _) | Address::PostIncrement(base) => reg == base,
+        }
+    }
+
+    pub fn mode(&self) -> u8 {
+        match self {
+            Address::Pair(high, low) => (*high as u8) << 3 | *low as u8,
+            Address::PairPostIncrement(high, low) => 1 << 6 | (*high as u8) << 3 | *low as u8,
+            Address::Offset(base, _) => 2 << 6 | (*base as u8) << 3,
+            Address::PostIncrement(base) => 3 << 6 | (*base as u8) << 3,
+        }
+    }
 }
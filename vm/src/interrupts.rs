@@ -1,7 +1,36 @@
-use crate::Machine;
-use anyhow::Result;
+use crate::{Machine, VmError};
 
-pub fn halt_interrupt(vm: &mut Machine) -> Result<()> {
+/// Number of interrupt signals, `Interrupt` only has 4 bits for one.
+pub const INTERRUPT_COUNT: usize = 16;
+
+/// Host code run by `Interrupt n`.
+///
+/// Closures taking the machine are handlers too, so handlers can own whatever host state
+/// they need (a channel, a buffer, a counter):
+///
+/// ```
+/// # use vm::Machine;
+/// let mut vm = Machine::new();
+/// let mut count = 0;
+/// vm.define_interrupt(0x1, move |_: &mut Machine| {
+///     count += 1;
+///     Ok(())
+/// });
+/// ```
+pub trait InterruptHandler {
+    fn handle(&mut self, vm: &mut Machine) -> Result<(), VmError>;
+}
+
+impl<F> InterruptHandler for F
+where
+    F: FnMut(&mut Machine) -> Result<(), VmError>,
+{
+    fn handle(&mut self, vm: &mut Machine) -> Result<(), VmError> {
+        self(vm)
+    }
+}
+
+pub fn halt_interrupt(vm: &mut Machine) -> Result<(), VmError> {
     vm.halt = true;
     Ok(())
 }
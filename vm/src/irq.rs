@@ -0,0 +1,58 @@
+use crate::interrupts::INTERRUPT_COUNT;
+
+/// The hardware interrupt lines devices raise, one per interrupt signal.
+///
+/// A raised line stays pending until the machine delivers it between two instructions,
+/// which it does while InterruptEnable is set. Lower lines win when several are pending,
+/// masked lines are kept pending but never delivered.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct IrqController {
+    pub(crate) pending: u16,
+    pub(crate) masked: u16,
+}
+
+impl IrqController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Panics if `line` isn't a valid signal (0 to 15).
+    pub fn raise(&mut self, line: u8) {
+        self.pending |= Self::bit(line);
+    }
+
+    /// Drops a pending interrupt on `line`.
+    pub fn lower(&mut self, line: u8) {
+        self.pending &= !Self::bit(line);
+    }
+
+    pub fn is_pending(&self, line: u8) -> bool {
+        self.pending & Self::bit(line) != 0
+    }
+
+    pub fn set_masked(&mut self, line: u8, masked: bool) {
+        if masked {
+            self.masked |= Self::bit(line);
+        } else {
+            self.masked &= !Self::bit(line);
+        }
+    }
+
+    pub fn is_masked(&self, line: u8) -> bool {
+        self.masked & Self::bit(line) != 0
+    }
+
+    /// The pending line that should be delivered next.
+    pub fn next(&self) -> Option<u8> {
+        let ready = self.pending & !self.masked;
+        (ready != 0).then(|| ready.trailing_zeros() as u8)
+    }
+
+    fn bit(line: u8) -> u16 {
+        assert!(
+            (line as usize) < INTERRUPT_COUNT,
+            "no interrupt line {line}"
+        );
+        1 << line
+    }
+}
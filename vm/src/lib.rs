@@ -1,7 +1,18 @@
+pub mod assembler;
+pub mod bus;
+pub mod devices;
+pub mod error;
 pub mod instructions;
 pub mod interrupts;
+pub mod irq;
 pub mod memory;
+pub mod protection;
 pub mod registers;
+pub mod trace;
 pub mod vm;
+pub mod watch;
 
-pub use crate::{instructions::*, interrupts::*, registers::*, vm::*};
+pub use crate::{
+    bus::*, error::*, instructions::*, interrupts::*, irq::*, memory::*, protection::*,
+    registers::*, trace::*, vm::*, watch::*,
+};
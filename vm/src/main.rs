@@ -1,29 +1,81 @@
 use anyhow::Result;
 use std::{
     env,
-    fs::File,
-    io::{BufReader, Read},
+    fs::{self, File},
+    io::{self, BufReader, Read},
     path::Path,
 };
 
-use vm::interrupts::halt_interrupt;
+use vm::devices::Console;
+use vm::trace::{JsonTracer, TextTracer};
 use vm::{Machine, Registers};
 
+const USAGE: &str =
+    "usage: main [--load-state FILE] [--save-state FILE] [--steps N] [--trace text|json] PROGRAM";
+
+/// How `--trace` prints what the machine does.
+enum Trace {
+    Text,
+    Json,
+}
+
+/// What the command line asked for.
+#[derive(Default)]
+struct Options {
+    /// Needed with `--load-state` too, loading it is what protects the code.
+    program: String,
+    load_state: Option<String>,
+    save_state: Option<String>,
+    steps: Option<u64>,
+    trace: Option<Trace>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut options = Options::default();
+    let mut program = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{arg} needs a value\n{USAGE}"))
+        };
+        match arg.as_str() {
+            "--load-state" => options.load_state = Some(value()?),
+            "--save-state" => options.save_state = Some(value()?),
+            "--steps" => options.steps = Some(value()?.parse()?),
+            "--trace" => {
+                options.trace = Some(match value()?.as_str() {
+                    "text" => Trace::Text,
+                    "json" => Trace::Json,
+                    format => {
+                        return Err(anyhow::anyhow!("unknown trace format {format}\n{USAGE}"))
+                    }
+                })
+            }
+            _ if arg.starts_with("--") => {
+                return Err(anyhow::anyhow!("unknown option {arg}\n{USAGE}"))
+            }
+            _ => program = Some(arg),
+        }
+    }
+    options.program =
+        program.ok_or_else(|| anyhow::anyhow!("where's the program file you dumbass!\n{USAGE}"))?;
+    Ok(options)
+}
+
 fn main() -> Result<()> {
-    let mut vm = Machine::new();
+    let options = parse_options()?;
+    let mut vm = Machine::standard(Console::stdio())?;
 
-    // vm.memory.write(0xfffe, 69)?;
+    // vm.bus.write(0xfffe, 69)?;
     //
-    // vm.memory.write(0, 0x70)?;
-    // vm.memory.write(1, 0xff)?;
-    // vm.memory.write(2, 0xfe)?;
-    // vm.memory.write(3, 0xff)?;
+    // vm.bus.write(0, 0x70)?;
+    // vm.bus.write(1, 0xff)?;
+    // vm.bus.write(2, 0xfe)?;
+    // vm.bus.write(3, 0xff)?;
     // vm.step()?;
 
-    let file =
-        File::open(Path::new(&env::args().nth(1).ok_or_else(|| {
-            anyhow::anyhow!("where's the program file you dumbass!")
-        })?))
+    let file = File::open(Path::new(&options.program))
         .map_err(|_| anyhow::anyhow!("can't open the file, try giving a valid path."))?;
 
     let mut bytes: Vec<u8> = Vec::new();
@@ -37,17 +89,32 @@ fn main() -> Result<()> {
             .collect::<Vec<_>>()
             .join(" ")
     );
-    vm.define_interrupt(0xF, halt_interrupt);
-    vm.memory.load(&bytes)?;
     if bytes.is_empty() {
         return Err(anyhow::anyhow!("empty binary"));
     }
-    while !vm.halt {
+    vm.load_program(&bytes)?;
+    // the snapshot brings back the memory, not the protection loading the program set up
+    if let Some(path) = &options.load_state {
+        vm.restore(&fs::read(path)?)?;
+    }
+    // on stderr, so it doesn't get mixed up with what the program prints
+    match options.trace {
+        Some(Trace::Text) => drop(vm.set_tracer(TextTracer::new(io::stderr()))),
+        Some(Trace::Json) => drop(vm.set_tracer(JsonTracer::new(io::stderr()))),
+        None => {}
+    }
+
+    let mut steps = 0;
+    while !vm.halt && options.steps.is_none_or(|limit| steps < limit) {
         vm.step()?;
-        println!("{}", vm.state());
+        steps += 1;
         // vm.clear_flag(Flags::Zero);
         // vm.clear_flag(Flags::Overflow);
     }
+    if let Some(path) = &options.save_state {
+        fs::write(path, vm.snapshot()?)?;
+    }
     println!("reg A = {}", vm.registers[Registers::A as usize]);
+    println!("max stack depth = {}", vm.max_stack_depth());
     Ok(())
 }
@@ -1,39 +1,49 @@
-use anyhow::Result;
+use crate::{bus::Bus, error::VmError};
 
+/// Flat RAM covering the whole 16-bit address space, the default backing of the machine.
 pub struct Memory {
     bytes: Vec<u8>,
-    size: u16,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Memory {
-    pub fn new(size: u16) -> Self {
+    pub fn new() -> Self {
         Self {
-            bytes: vec![0; size.into()],
-            size,
+            bytes: vec![0; 0x10000],
         }
     }
 
-    pub fn read(&self, addr: u16) -> Result<u8> {
-        if addr < self.size {
-            Ok(self.bytes[addr as usize])
-        } else {
-            Err(anyhow::anyhow!("address {} out of bounds", addr))
-        }
+    pub fn read(&self, addr: u16) -> u8 {
+        self.bytes[addr as usize]
     }
 
-    pub fn write(&mut self, addr: u16, value: u8) -> Result<()> {
-        if addr < self.size {
-            self.bytes[addr as usize] = value;
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("address {} out of bounds", addr))
-        }
+    pub fn write(&mut self, addr: u16, value: u8) {
+        self.bytes[addr as usize] = value;
     }
 
-    pub fn load(&mut self, program_vec: &[u8]) -> Result<()> {
-        for (index, byte) in program_vec.iter().enumerate() {
-            self.write(index as u16, *byte)?
-        }
+    /// Copies `program` to address 0 on.
+    pub fn load(&mut self, program: &[u8]) {
+        let size = program.len().min(self.bytes.len());
+        self.bytes[..size].copy_from_slice(&program[..size]);
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, addr: u16) -> Result<u8, VmError> {
+        Ok(Memory::read(self, addr))
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), VmError> {
+        Memory::write(self, addr, value);
         Ok(())
     }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(Memory::read(self, addr))
+    }
 }
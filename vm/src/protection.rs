@@ -0,0 +1,94 @@
+use crate::error::{Access, VmError};
+
+/// What a program may do with a region of memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const NONE: Self = Self::new(false, false, false);
+    pub const READ: Self = Self::new(true, false, false);
+    pub const READ_WRITE: Self = Self::new(true, true, false);
+    pub const READ_EXECUTE: Self = Self::new(true, false, true);
+    pub const ALL: Self = Self::new(true, true, true);
+
+    pub const fn new(read: bool, write: bool, execute: bool) -> Self {
+        Self {
+            read,
+            write,
+            execute,
+        }
+    }
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Region {
+    start: u16,
+    end: u16,
+    permissions: Permissions,
+}
+
+/// Permissions of regions of the address space, checked on every access the machine makes.
+#[derive(Debug, Clone, Default)]
+pub struct Protection {
+    regions: Vec<Region>,
+}
+
+impl Protection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts what programs may do with the `size` bytes starting at `start`.
+    ///
+    /// Addresses outside every region allow everything. Regions can overlap, the one
+    /// protected last wins, so a region can be carved out of a bigger one.
+    pub fn protect(&mut self, start: u16, size: u16, permissions: Permissions) {
+        if size == 0 {
+            return;
+        }
+        let end = start.saturating_add(size - 1);
+        self.regions.push(Region {
+            start,
+            end,
+            permissions,
+        });
+    }
+
+    /// Drops every region, the whole address space allows everything again.
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    pub fn permissions(&self, addr: u16) -> Permissions {
+        self.regions
+            .iter()
+            .rev()
+            .find(|region| region.start <= addr && addr <= region.end)
+            .map_or(Permissions::ALL, |region| region.permissions)
+    }
+
+    pub fn check(&self, addr: u16, access: Access) -> Result<(), VmError> {
+        if self.permissions(addr).allows(access) {
+            Ok(())
+        } else {
+            Err(VmError::Protection {
+                pc: 0,
+                opcode: 0,
+                addr,
+                access,
+            })
+        }
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, Copy, Clone)]
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Registers {
     A,
@@ -9,15 +11,46 @@ pub enum Registers {
     PC,
     BP,
     Flags,
+    AB, // A:B, A is the high byte
+    CD, // C:D, C is the high byte
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum Flags {
-    Zero = 1 << 1,
-    Overflow = 1 << 0,
+    Carry = 1 << 0,           // unsigned carry out, or borrow after a subtraction
+    Zero = 1 << 1,            // result was zero
+    Negative = 1 << 2,        // top bit of the result
+    Overflow = 1 << 3,        // signed (two's complement) overflow
+    InterruptEnable = 1 << 4, // guest interrupt handlers may run
 }
 
 impl Registers {
+    /// SP, PC and BP hold addresses, so they are 16 bits wide, and so are the pairs.
+    pub fn is_wide(self) -> bool {
+        matches!(
+            self,
+            Registers::SP | Registers::PC | Registers::BP | Registers::AB | Registers::CD
+        )
+    }
+
+    /// The high and low register making up a register pair.
+    pub fn halves(self) -> Option<(Registers, Registers)> {
+        match self {
+            Registers::AB => Some((Registers::A, Registers::B)),
+            Registers::CD => Some((Registers::C, Registers::D)),
+            _ => None,
+        }
+    }
+
+    /// Bits of the register that are actually stored.
+    pub fn mask(self) -> u16 {
+        if self.is_wide() {
+            0xFFFF
+        } else {
+            0xFF
+        }
+    }
+
     pub fn from_u8_custom(value: u8) -> Option<Self> {
         match value {
             0 => Some(Registers::A),
@@ -28,6 +61,8 @@ impl Registers {
             5 => Some(Registers::PC),
             6 => Some(Registers::BP),
             7 => Some(Registers::Flags),
+            8 => Some(Registers::AB),
+            9 => Some(Registers::CD),
             _ => None,
         }
     }
@@ -42,7 +77,19 @@ impl Registers {
             "PC" => Some(Registers::PC),
             "BP" => Some(Registers::BP),
             "Flags" => Some(Registers::Flags),
+            "A:B" => Some(Registers::AB),
+            "C:D" => Some(Registers::CD),
             _ => None,
         }
     }
 }
+
+/// The name the assembler knows the register by.
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.halves() {
+            Some((high, low)) => write!(f, "{high:?}:{low:?}"),
+            None => write!(f, "{self:?}"),
+        }
+    }
+}
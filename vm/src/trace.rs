@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use crate::{instructions::Instruction, registers::Registers};
+
+/// Something that happened while the machine was stepping.
+///
+/// Events come in the order they happen: the fetches of an instruction, its decoding and the
+/// memory writes it makes, then the registers it changed once it went through. A faulting
+/// instruction changes no register, so none are reported for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent<'a> {
+    /// A byte of an instruction was fetched from `addr`.
+    Fetch { addr: u16, byte: u8 },
+    /// The instruction at `pc` was decoded.
+    Decode {
+        pc: u16,
+        instruction: &'a Instruction,
+    },
+    /// A register changed over the last instruction.
+    Register {
+        register: Registers,
+        old: u16,
+        new: u16,
+    },
+    /// The program wrote `value` to `addr`.
+    Memory { addr: u16, value: u8 },
+    /// Interrupt `signal` was raised, going to the guest handler at `vector` or the host
+    /// handler when there is none.
+    Interrupt { signal: u8, vector: Option<u16> },
+}
+
+/// Gets every event of a running machine, set with `Machine::set_tracer`.
+pub trait Tracer {
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+/// Ignores everything, the default tracer of a machine.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoTracer;
+
+impl Tracer for NoTracer {
+    fn trace(&mut self, _event: &TraceEvent) {}
+}
+
+/// Writes one readable line per event.
+pub struct TextTracer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        // a broken trace output shouldn't stop the machine
+        let _ = match *event {
+            TraceEvent::Fetch { addr, byte } => {
+                writeln!(self.out, "fetch     0x{addr:04X}: 0x{byte:02X}")
+            }
+            TraceEvent::Decode { pc, instruction } => {
+                writeln!(self.out, "decode    0x{pc:04X}: {instruction:?}")
+            }
+            TraceEvent::Register { register, old, new } => {
+                writeln!(
+                    self.out,
+                    "register  {register:?}: 0x{old:04X} -> 0x{new:04X}"
+                )
+            }
+            TraceEvent::Memory { addr, value } => {
+                writeln!(self.out, "memory    0x{addr:04X} <- 0x{value:02X}")
+            }
+            TraceEvent::Interrupt { signal, vector } => match vector {
+                Some(vector) => writeln!(self.out, "interrupt {signal} -> 0x{vector:04X}"),
+                None => writeln!(self.out, "interrupt {signal} -> host"),
+            },
+        };
+    }
+}
+
+/// Writes every event as a JSON object on its own line (JSON Lines).
+pub struct JsonTracer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        let _ = match *event {
+            TraceEvent::Fetch { addr, byte } => writeln!(
+                self.out,
+                r#"{{"event":"fetch","addr":{addr},"byte":{byte}}}"#
+            ),
+            TraceEvent::Decode { pc, instruction } => writeln!(
+                self.out,
+                r#"{{"event":"decode","pc":{pc},"instruction":"{}"}}"#,
+                escape(&format!("{instruction:?}"))
+            ),
+            TraceEvent::Register { register, old, new } => writeln!(
+                self.out,
+                r#"{{"event":"register","register":"{register:?}","old":{old},"new":{new}}}"#
+            ),
+            TraceEvent::Memory { addr, value } => writeln!(
+                self.out,
+                r#"{{"event":"memory","addr":{addr},"value":{value}}}"#
+            ),
+            TraceEvent::Interrupt { signal, vector } => writeln!(
+                self.out,
+                r#"{{"event":"interrupt","signal":{signal},"vector":{}}}"#,
+                vector.map_or("null".to_string(), |vector| vector.to_string())
+            ),
+        };
+    }
+}
+
+/// Makes `text` safe to put between the quotes of a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,22 +1,54 @@
-use anyhow::Result;
-use std::collections::HashMap;
+mod journal;
+mod snapshot;
+
+pub use journal::CHECKPOINT_INTERVAL;
+pub use snapshot::SNAPSHOT_VERSION;
 
 use crate::{
-    instructions::{ALUOperation, Instruction, JumpTarget},
+    bus::{Bus, SystemBus},
+    devices::{
+        bank::{BANK_BASE, BANK_COUNT, BANK_WINDOW},
+        console::{CONSOLE_BASE, CONSOLE_SIZE},
+        timer::{TIMER_BASE, TIMER_SIZE},
+        BankedMemory, Console, Timer,
+    },
+    error::{Access, StackFault, VmError},
+    instructions::{ALUOperation, Address, Instruction, JumpTarget, DECODE_FAULT},
+    interrupts::{halt_interrupt, InterruptHandler, INTERRUPT_COUNT},
+    irq::IrqController,
     memory::Memory,
+    protection::Permissions,
     registers::{Flags, Registers},
+    trace::{NoTracer, TraceEvent, Tracer},
+    watch::{WatchAction, WatchHit, WatchId, Watchpoints},
     JumpCondition,
 };
 
-type Interrupt = fn(&mut Machine) -> Result<()>;
+/// The stack starts at the top of memory and gets 4 KiB to grow down into.
+pub const DEFAULT_STACK_BASE: u16 = 0xffff;
+pub const DEFAULT_STACK_LIMIT: u16 = 0xf000;
+/// The interrupt vector table sits right below the stack.
+const DEFAULT_IVT_BASE: u16 = 0xefe0;
 
 pub struct Machine {
-    pub registers: [u8; 8],
+    /// Indexed by `Registers`. SP, PC and BP use all 16 bits, the rest only the low byte.
+    pub registers: [u16; 8],
     pub halt: bool,
-    pub memory: Memory,
-    pub pc: u16,
-    pub sp: u16,
-    interrupts: HashMap<u8, Interrupt>,
+    /// Every load, store, push, pop and fetch goes through here.
+    pub bus: SystemBus,
+    /// Devices raise hardware interrupts here.
+    pub irq: IrqController,
+    interrupts: [Option<Box<dyn InterruptHandler>>; INTERRUPT_COUNT],
+    stack_base: u16,
+    stack_limit: u16,
+    max_stack_depth: u16,
+    ivt_base: u16,
+    cycles: u64,
+    watchpoints: Watchpoints,
+    journal: Option<journal::Journal>,
+    tracer: Box<dyn Tracer>,
+    /// Address of the instruction being executed.
+    instruction: u16,
 }
 
 impl Default for Machine {
@@ -27,192 +59,722 @@ impl Default for Machine {
 
 #[allow(dead_code)]
 impl Machine {
+    /// A machine on 64 KiB of flat RAM.
     pub fn new() -> Self {
-        Self {
+        Self::with_bus(Memory::new())
+    }
+
+    /// A machine whose memory accesses go to `bus`, apart from the ones to mapped devices.
+    pub fn with_bus(bus: impl Bus + 'static) -> Self {
+        let mut machine = Self {
             registers: [0; 8],
             halt: false,
-            interrupts: HashMap::new(),
-            pc: 0,
-            sp: 0,
-            memory: Memory::new(0xffff),
-        }
+            interrupts: Default::default(),
+            bus: SystemBus::new(bus),
+            irq: IrqController::new(),
+            stack_base: 0,
+            stack_limit: 0,
+            max_stack_depth: 0,
+            ivt_base: DEFAULT_IVT_BASE,
+            cycles: 0,
+            watchpoints: Watchpoints::default(),
+            journal: None,
+            tracer: Box::new(NoTracer),
+            instruction: 0,
+        };
+        machine.set_stack(DEFAULT_STACK_BASE, DEFAULT_STACK_LIMIT);
+        machine
+    }
+
+    /// The machine the binaries run programs on: `console`, a timer on IRQ line 0 and the
+    /// banked memory mapped at their usual addresses, `Interrupt 15` halting and the stack
+    /// readable and writable only.
+    pub fn standard(console: Console) -> Result<Self, VmError> {
+        let mut machine = Self::new();
+        machine.define_interrupt(0xF, halt_interrupt);
+        machine
+            .bus
+            .map(CONSOLE_BASE, CONSOLE_SIZE, Box::new(console))?;
+        machine
+            .bus
+            .map(TIMER_BASE, TIMER_SIZE, Box::new(Timer::new(0)))?;
+        let banks = BankedMemory::new(BANK_COUNT, BANK_WINDOW);
+        machine.bus.map(BANK_BASE, banks.size(), Box::new(banks))?;
+        machine.bus.protection.protect(
+            DEFAULT_STACK_LIMIT,
+            DEFAULT_STACK_BASE - DEFAULT_STACK_LIMIT,
+            Permissions::READ_WRITE,
+        );
+        Ok(machine)
+    }
+
+    /// Loads `program` at address 0 and makes it readable and executable only, so it can't
+    /// overwrite itself. Programs over 0xFFFF bytes don't leave room for anything else.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), VmError> {
+        let size = u16::try_from(program.len()).map_err(|_| VmError::ProgramSize {
+            size: program.len(),
+        })?;
+        self.bus.load(program)?;
+        self.bus
+            .protection
+            .protect(0, size, Permissions::READ_EXECUTE);
+        Ok(())
+    }
+
+    /// Moves the stack to grow down from `base` (SP of an empty stack) to `limit` (the
+    /// lowest address a push may write) and empties it.
+    pub fn set_stack(&mut self, base: u16, limit: u16) {
+        self.stack_base = base;
+        self.stack_limit = limit;
+        self.max_stack_depth = 0;
+        self.set_register(Registers::SP, base);
+    }
+
+    /// Moves the interrupt vector table, `base` is the address of the vector of `Interrupt 0`.
+    pub fn set_ivt(&mut self, base: u16) {
+        self.ivt_base = base;
+    }
+
+    /// Watches the program's `access`es of the `size` addresses from `start` (at least one),
+    /// covering loads, stores, pushes, pops, interrupt vectors and instruction fetches.
+    pub fn watch(&mut self, start: u16, size: u16, access: Access, action: WatchAction) -> WatchId {
+        self.watchpoints.add(start, size, access, action)
+    }
+
+    /// Removes a watchpoint, false if it was already gone.
+    pub fn unwatch(&mut self, id: WatchId) -> bool {
+        self.watchpoints.remove(id)
+    }
+
+    /// Sends the events of every following step to `tracer`, handing back the one it replaces.
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'static) -> Box<dyn Tracer> {
+        std::mem::replace(&mut self.tracer, Box::new(tracer))
+    }
+
+    /// Bytes currently on the stack.
+    pub fn stack_depth(&self) -> u16 {
+        self.stack_base.wrapping_sub(self.sp())
+    }
+
+    /// The deepest the stack got since the last `set_stack`.
+    pub fn max_stack_depth(&self) -> u16 {
+        self.max_stack_depth
     }
 
     pub fn state(&self) -> String {
+        // devices with something to show (like the selected bank) go at the end
+        let mut devices = self.bus.status();
+        if !devices.is_empty() {
+            devices.insert_str(0, " | ");
+        }
         format!(
-            "A: {} | B: {} | C: {} | D: {} SP: {} | PC: {} | BP: {} Flags: {:X}\n",
+            "A: {} | B: {} | C: {} | D: {} SP: {} | PC: {} | BP: {} Flags: {:X}{}\n",
             self.get_register(Registers::A),
             self.get_register(Registers::B),
             self.get_register(Registers::C),
             self.get_register(Registers::D),
-            self.sp,
-            self.pc,
+            self.sp(),
+            self.pc(),
             self.get_register(Registers::BP),
-            self.get_register(Registers::Flags)
+            self.get_register(Registers::Flags),
+            devices
         )
     }
 
-    pub fn define_interrupt(&mut self, index: u8, f: Interrupt) {
-        self.interrupts.insert(index, f);
+    /// Makes `handler` run on `Interrupt index`, handing back the handler it replaces.
+    ///
+    /// Panics if `index` isn't a valid signal (0 to 15).
+    pub fn define_interrupt(
+        &mut self,
+        index: u8,
+        handler: impl InterruptHandler + 'static,
+    ) -> Option<Box<dyn InterruptHandler>> {
+        assert!(
+            (index as usize) < INTERRUPT_COUNT,
+            "no interrupt signal {index}"
+        );
+        self.interrupts[index as usize].replace(Box::new(handler))
+    }
+
+    /// Unregisters the handler of `Interrupt index` and hands it back.
+    pub fn remove_interrupt(&mut self, index: u8) -> Option<Box<dyn InterruptHandler>> {
+        self.interrupts.get_mut(index as usize)?.take()
+    }
+
+    pub fn get_register(&self, r: Registers) -> u16 {
+        match r.halves() {
+            Some((high, low)) => self.get_register(high) << 8 | self.get_register(low),
+            None => self.registers[r as usize],
+        }
+    }
+
+    /// Writes `v` into `r`, 8-bit registers keep only the low byte.
+    pub fn set_register(&mut self, r: Registers, v: u16) {
+        match r.halves() {
+            Some((high, low)) => {
+                self.set_register(high, v >> 8);
+                self.set_register(low, v);
+            }
+            None => self.registers[r as usize] = v & r.mask(),
+        }
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.get_register(Registers::PC)
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.get_register(Registers::SP)
     }
 
-    pub fn get_register(&self, r: Registers) -> u8 {
-        self.registers[r as usize]
+    /// Steps taken so far, the devices tick once per step.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
 
-    pub fn set_register(&mut self, r: Registers, v: u8) {
-        self.registers[r as usize] = v;
+    /// Executes one instruction, or enters the handler of a pending hardware interrupt when
+    /// InterruptEnable is set.
+    ///
+    /// Faults are precise: when `step` returns an error the registers are back to what they
+    /// were before the instruction, so PC points at the faulting instruction and the host can
+    /// fix things up (or move PC past it) and carry on stepping.
+    pub fn step(&mut self) -> Result<(), VmError> {
+        let pc = self.pc();
+        if self.halt {
+            return Err(VmError::Halted { pc });
+        }
+        self.journal_begin()?;
+        let registers = self.registers;
+        let irq = self.irq;
+        let max_stack_depth = self.max_stack_depth;
+        self.instruction = pc;
+        self.watchpoints.stop = None;
+        let line = self
+            .irq
+            .next()
+            .filter(|_| self.is_flag_set(Flags::InterruptEnable));
+        let result = match line {
+            Some(line) => {
+                self.irq.lower(line);
+                self.interrupt(line).map_err(|err| match err {
+                    VmError::UnknownInterrupt { .. } => VmError::UnhandledIrq { pc, line },
+                    err => err.at(pc, 0),
+                })
+            }
+            None => self
+                .fetch()
+                .map_err(|err| err.at(pc, 0))
+                .and_then(|opcode| self.execute(opcode).map_err(|err| err.at(pc, opcode))),
+        };
+        if let Err(err) = result {
+            self.registers = registers;
+            self.irq = irq;
+            self.max_stack_depth = max_stack_depth;
+            if let VmError::UnhandledIrq { line, .. } = err {
+                // pending again it would fail every step from now on
+                self.irq.lower(line);
+            }
+            return Err(err);
+        }
+        self.journal_commit(registers, irq, max_stack_depth);
+        for (index, (old, new)) in registers.into_iter().zip(self.registers).enumerate() {
+            if old != new {
+                let register = Registers::from_u8_custom(index as u8).unwrap();
+                self.tracer
+                    .trace(&TraceEvent::Register { register, old, new });
+            }
+        }
+        self.cycles += 1;
+        self.bus.tick(&mut self.irq);
+        match self.watchpoints.stop.take() {
+            Some(hit) => Err(VmError::Watchpoint {
+                pc: hit.pc,
+                addr: hit.addr,
+                access: hit.access,
+                old: hit.old,
+                new: hit.new,
+            }),
+            None => Ok(()),
+        }
     }
 
-    pub fn step(&mut self) -> Result<()> {
-        let opcode = self.fetch()?;
+    fn execute(&mut self, opcode: u8) -> Result<(), VmError> {
         let instruction = self.decode(opcode)?;
-        println!(
-            "\nPC -> {:?}   |   OPCODE -> 0x{:X}   |   INST -> {:?}",
-            // self.registers[Registers::PC as usize],
-            self.pc,
-            opcode >> 4,
-            instruction,
-        );
+        self.tracer.trace(&TraceEvent::Decode {
+            pc: self.instruction,
+            instruction: &instruction,
+        });
 
-        let _ = match instruction {
+        match instruction {
             Instruction::Nop => Ok(()),
             Instruction::Push(value) => self.push(value),
             Instruction::PopRegister(r) => {
-                let value = self.pop()?;
-                self.registers[r as usize] = value;
+                let value = if r.is_wide() {
+                    self.pop_word()?
+                } else {
+                    self.pop()? as u16
+                };
+                self.set_register(r, value);
                 Ok(())
             }
             Instruction::PushRegister(r) => {
-                self.push(self.registers[r as usize])?;
-                Ok(())
+                let value = self.get_register(r);
+                if r.is_wide() {
+                    self.push_word(value)
+                } else {
+                    self.push(value as u8)
+                }
             }
             Instruction::AddStack => {
                 let a = self.pop()?;
                 let b = self.pop()?;
-                let (result, overflow) = a.overflowing_add(b);
-                self.push(result)?;
-                self.set_flag(Flags::Overflow, overflow);
-                Ok(())
+                let result = self.alu(ALUOperation::Add, a as u16, b as u16, false)?;
+                self.push(result as u8)
             }
             Instruction::LoadImmediate(reg, value) => {
                 self.set_register(reg, value);
                 Ok(())
             }
-            Instruction::LoadMemory(reg, memaddress) => {
-                let value = self.memory.read(memaddress)?;
-                self.set_register(reg, value);
+            Instruction::LoadMemory(reg, memaddress) => self.load_register(reg, memaddress),
+            Instruction::Store(reg, memaddress) => self.store_register(reg, memaddress),
+            Instruction::LoadIndirect(reg, address) => {
+                let memaddress = self.effective_address(address);
+                self.load_register(reg, memaddress)?;
+                // loading into the base, the loaded value wins over the increment
+                if !address.uses(reg) {
+                    self.post_increment(address, reg, memaddress);
+                }
                 Ok(())
             }
-            Instruction::Store(reg, memaddress) => {
-                let value = self.get_register(reg);
-                self.memory.write(memaddress, value)?;
-                // println!("{:?}, {}", reg, self.memory.read(memaddress)?);
+            Instruction::StoreIndirect(reg, address) => {
+                let memaddress = self.effective_address(address);
+                self.store_register(reg, memaddress)?;
+                self.post_increment(address, reg, memaddress);
                 Ok(())
             }
             Instruction::ALU(operation, reg1, reg2) => {
-                let (result, overflow) = match operation {
-                    ALUOperation::Add => self
-                        .get_register(reg1)
-                        .overflowing_add(self.get_register(reg2)),
-                    ALUOperation::Sub => self
-                        .get_register(reg1)
-                        .overflowing_sub(self.get_register(reg2)),
-                    ALUOperation::Mul => self
-                        .get_register(reg1)
-                        .overflowing_mul(self.get_register(reg2)),
-                    ALUOperation::Div => self
-                        .get_register(reg1)
-                        .overflowing_div(self.get_register(reg2)),
-                };
-
-                // println!("overflow = {overflow}");
-
-                self.set_register(reg1, result);
-                self.set_flag(Flags::Zero, result == 0);
-                self.set_flag(Flags::Overflow, overflow);
-
-                Ok(())
+                self.alu_register(operation, reg1, self.get_register(reg2) & reg1.mask())
+            }
+            Instruction::ALUImmediate(operation, reg, value) => {
+                self.alu_register(operation, reg, value as u16)
             }
             Instruction::Jump(address) => {
-                match address {
-                    JumpTarget::Address(addr) => self.pc = addr,
-                    _ => todo!(),
-                }
+                self.set_register(Registers::PC, target(address)?);
                 Ok(())
             }
             Instruction::JumpConditional(condition, address) => {
                 // println!("here in JumpConditional");
+                let carry = self.is_flag_set(Flags::Carry);
+                let zero = self.is_flag_set(Flags::Zero);
+                // signed "less than" is when the sign of the result lies because of overflow
+                let less = self.is_flag_set(Flags::Negative) != self.is_flag_set(Flags::Overflow);
                 let should_jump = match condition {
-                    JumpCondition::EQ => self.is_flag_set(Flags::Zero),
-                    JumpCondition::NEQ => !self.is_flag_set(Flags::Zero),
-                    JumpCondition::LT => self.is_flag_set(Flags::Overflow),
-                    _ => todo!(),
+                    JumpCondition::EQ => zero,
+                    JumpCondition::NEQ => !zero,
+                    JumpCondition::LT => carry,
+                    JumpCondition::GT => !carry && !zero,
+                    JumpCondition::GE => !carry,
+                    JumpCondition::LE => carry || zero,
+                    JumpCondition::SLT => less,
+                    JumpCondition::SGT => !less && !zero,
+                    JumpCondition::SGE => !less,
+                    JumpCondition::SLE => less || zero,
                 };
                 if should_jump {
-                    match address {
-                        JumpTarget::Address(addr) => self.pc = addr,
-                        _ => todo!(),
-                    }
+                    self.set_register(Registers::PC, target(address)?);
                 }
                 Ok(())
             }
-            Instruction::Interrupt(signal) => {
-                let signal_function = self.interrupts.get(&signal).ok_or(anyhow::anyhow!(
-                    "0x{:X} is not a valid signal, dumbass!",
-                    signal
-                ))?;
-                signal_function(self)
-            } // _ => todo!(),
+            Instruction::Call(address) => {
+                let addr = target(address)?;
+                // the return address is the instruction right after the call
+                self.push_word(self.pc())?;
+                self.set_register(Registers::PC, addr);
+                Ok(())
+            }
+            Instruction::Ret => {
+                let addr = self.pop_word()?;
+                self.set_register(Registers::PC, addr);
+                Ok(())
+            }
+            Instruction::Enter(size) => {
+                // save the caller's frame, the new one starts at the current top of stack
+                // and the next `size` bytes are reserved for locals
+                self.push_word(self.get_register(Registers::BP))?;
+                self.set_register(Registers::BP, self.sp());
+                self.grow_stack(size as u16)?;
+                Ok(())
+            }
+            Instruction::Leave => {
+                self.set_register(Registers::SP, self.get_register(Registers::BP));
+                let bp = self.pop_word()?;
+                self.set_register(Registers::BP, bp);
+                Ok(())
+            }
+            Instruction::Halt => {
+                self.halt = true;
+                Ok(())
+            }
+            Instruction::Move(dst, src) => {
+                self.set_register(dst, self.get_register(src));
+                Ok(())
+            }
+            Instruction::Iret => {
+                let pc = self.pop_word()?;
+                let flags = self.pop()?;
+                self.set_register(Registers::PC, pc);
+                self.set_register(Registers::Flags, flags as u16);
+                Ok(())
+            }
+            Instruction::Ei => {
+                self.set_flag(Flags::InterruptEnable, true);
+                Ok(())
+            }
+            Instruction::Di => {
+                self.set_flag(Flags::InterruptEnable, false);
+                Ok(())
+            }
+            Instruction::Interrupt(signal) => self.interrupt(signal), // _ => todo!(),
+        }
+    }
+
+    /// Runs the guest handler of `signal` if there is one, the host handler otherwise.
+    fn interrupt(&mut self, signal: u8) -> Result<(), VmError> {
+        let vector = self.guest_vector(signal)?;
+        self.tracer.trace(&TraceEvent::Interrupt { signal, vector });
+        if let Some(vector) = vector {
+            return self.enter_interrupt(vector);
+        }
+        // the handler is taken out while it runs so it can have the machine, it goes
+        // back afterwards unless it registered a replacement for itself
+        let mut handler = self
+            .interrupts
+            .get_mut(signal as usize)
+            .and_then(Option::take)
+            .ok_or(VmError::UnknownInterrupt {
+                pc: 0,
+                opcode: 0,
+                signal,
+            })?;
+        let result = handler.handle(self);
+        self.interrupts[signal as usize].get_or_insert(handler);
+        result
+    }
+
+    /// Runs `operation` on `a` and `b`, updating every flag from the result.
+    ///
+    /// The operation is 16 bits wide when `wide` is set and 8 bits otherwise. Carry is the
+    /// unsigned carry out (or borrow for `Sub`/`Cmp`/`Sbc`, or the last bit shifted out), Overflow
+    /// is the signed overflow of the same operation treating both operands as two's
+    /// complement. The bitwise operations clear both.
+    fn alu(&mut self, operation: ALUOperation, a: u16, b: u16, wide: bool) -> Result<u16, VmError> {
+        let (mask, sign) = if wide {
+            (0xFFFF_u32, 0x8000_u32)
+        } else {
+            (0xFF_u32, 0x80_u32)
+        };
+        let (a, b) = (a as u32 & mask, b as u32 & mask);
+        let signed = |v: u32| {
+            if v & sign != 0 {
+                v as i32 - (mask as i32 + 1)
+            } else {
+                v as i32
+            }
         };
+
+        let (result, carry, overflow) = match operation {
+            ALUOperation::Add | ALUOperation::Adc => {
+                let carry_in =
+                    matches!(operation, ALUOperation::Adc) && self.is_flag_set(Flags::Carry);
+                let result = a + b + carry_in as u32;
+                (
+                    result,
+                    result > mask,
+                    (a ^ result) & (b ^ result) & sign != 0,
+                )
+            }
+            ALUOperation::Sub | ALUOperation::Cmp | ALUOperation::Sbc => {
+                let borrow_in =
+                    matches!(operation, ALUOperation::Sbc) && self.is_flag_set(Flags::Carry);
+                let result = a.wrapping_sub(b).wrapping_sub(borrow_in as u32);
+                (
+                    result,
+                    a < b + borrow_in as u32,
+                    (a ^ b) & (a ^ result) & sign != 0,
+                )
+            }
+            ALUOperation::Mul => {
+                let product = signed(a) * signed(b);
+                let result = a * b;
+                (
+                    result,
+                    result > mask,
+                    product < -(sign as i32) || product >= sign as i32,
+                )
+            }
+            ALUOperation::Div | ALUOperation::Mod if b == 0 => {
+                return Err(VmError::DivideByZero { pc: 0, opcode: 0 });
+            }
+            ALUOperation::Div => (a / b, false, false),
+            ALUOperation::Mod => (a % b, false, false),
+            ALUOperation::And => (a & b, false, false),
+            ALUOperation::Or => (a | b, false, false),
+            ALUOperation::Xor => (a ^ b, false, false),
+            ALUOperation::Not => (!a, false, false),
+            ALUOperation::Shl | ALUOperation::Shr | ALUOperation::Rcl | ALUOperation::Rcr => {
+                // one bit at a time, Carry ends up holding the last bit shifted out; the
+                // rotates feed the old Carry back in on the other side
+                let rotate = matches!(operation, ALUOperation::Rcl | ALUOperation::Rcr);
+                let bits = mask.count_ones() + 1;
+                let count = if rotate { b % bits } else { b.min(bits) };
+                let mut result = a;
+                let mut carry = self.is_flag_set(Flags::Carry);
+                for _ in 0..count {
+                    let carry_in = if rotate { carry as u32 } else { 0 };
+                    match operation {
+                        ALUOperation::Shl | ALUOperation::Rcl => {
+                            carry = result & sign != 0;
+                            result = (result << 1 | carry_in) & mask;
+                        }
+                        _ => {
+                            carry = result & 1 != 0;
+                            result = result >> 1 | if carry_in != 0 { sign } else { 0 };
+                        }
+                    }
+                }
+                (result, carry, false)
+            }
+        };
+        let result = result & mask;
+
+        self.set_flag(Flags::Carry, carry);
+        self.set_flag(Flags::Zero, result == 0);
+        self.set_flag(Flags::Negative, result & sign != 0);
+        self.set_flag(Flags::Overflow, overflow);
+        Ok(result as u16)
+    }
+
+    /// `reg = reg operation value`, at the width of `reg`.
+    fn alu_register(
+        &mut self,
+        operation: ALUOperation,
+        reg: Registers,
+        value: u16,
+    ) -> Result<(), VmError> {
+        let result = self.alu(operation, self.get_register(reg), value, reg.is_wide())?;
+        // Cmp is a subtraction that only keeps the flags
+        if !matches!(operation, ALUOperation::Cmp) {
+            self.set_register(reg, result);
+        }
         Ok(())
     }
 
-    fn push(&mut self, v: u8) -> Result<()> {
-        // let sp = self.registers[Registers::SP as usize];
-        let sp = self.sp;
-        // println!("sp = {sp}");
-        if self.memory.write(sp, v).is_err() {
-            return Err(anyhow::anyhow!("memory write fault @ 0x{:X}", sp));
+    fn load_register(&mut self, reg: Registers, memaddress: u16) -> Result<(), VmError> {
+        let mut value = self.read(memaddress)? as u16;
+        if reg.is_wide() {
+            // 16-bit registers live in memory low byte first
+            value |= (self.read(memaddress.wrapping_add(1))? as u16) << 8;
         }
-        // self.registers[Registers::SP as usize] += 1;
-        self.sp += 1;
+        self.set_register(reg, value);
         Ok(())
     }
 
-    fn pop(&mut self) -> Result<u8> {
-        // let sp = self.registers[Registers::SP as usize] - 1;
-        // let sp = self.sp - 1;
-        let sp = self.sp.wrapping_sub(1);
-        if let Ok(v) = self.memory.read(sp) {
-            // self.registers[Registers::SP as usize] -= 1;
-            self.sp = self.sp.wrapping_sub(1);
-            Ok(v)
+    fn store_register(&mut self, reg: Registers, memaddress: u16) -> Result<(), VmError> {
+        let value = self.get_register(reg);
+        if reg.is_wide() {
+            self.write_word(memaddress, value)
         } else {
-            Err(anyhow::anyhow!("memory read fault @ 0x{:X}", sp))
+            self.write(memaddress, value as u8)
+        }
+    }
+
+    fn pair(&self, high: Registers, low: Registers) -> u16 {
+        (self.get_register(high) & 0xFF) << 8 | self.get_register(low) & 0xFF
+    }
+
+    fn effective_address(&self, address: Address) -> u16 {
+        match address {
+            Address::Pair(high, low) | Address::PairPostIncrement(high, low) => {
+                self.pair(high, low)
+            }
+            Address::Offset(base, offset) => {
+                self.get_register(base).wrapping_add_signed(offset as i16)
+            }
+            Address::PostIncrement(base) => self.get_register(base),
+        }
+    }
+
+    /// Moves a post-increment address past the `reg` sized value just accessed at
+    /// `memaddress`.
+    fn post_increment(&mut self, address: Address, reg: Registers, memaddress: u16) {
+        let size = if reg.is_wide() { 2 } else { 1 };
+        let next = memaddress.wrapping_add(size);
+        match address {
+            Address::PairPostIncrement(high, low) => {
+                self.set_register(high, next >> 8);
+                self.set_register(low, next & 0xFF);
+            }
+            Address::PostIncrement(base) => self.set_register(base, next),
+            Address::Pair(..) | Address::Offset(..) => {}
+        }
+    }
+
+    /// The guest handler of `signal`, if guest interrupts are enabled and it installed one.
+    fn guest_vector(&mut self, signal: u8) -> Result<Option<u16>, VmError> {
+        if !self.is_flag_set(Flags::InterruptEnable) {
+            return Ok(None);
         }
+        // vectors are 16-bit addresses stored low byte first, 0 means "no handler"
+        let entry = self.ivt_base.wrapping_add(signal as u16 * 2);
+        let vector = self.read(entry)? as u16 | (self.read(entry.wrapping_add(1))? as u16) << 8;
+        Ok(Some(vector).filter(|vector| *vector != 0))
     }
 
-    fn fetch(&mut self) -> Result<u8> {
-        // let pc = self.registers[Registers::PC as usize];
-        let opcode = self.memory.read(self.pc)?;
-        // self.registers[Registers::PC as usize] = pc.wrapping_add(1);
-        self.pc = self.pc.wrapping_add(1);
+    /// Saves Flags and PC for `Iret` and jumps to the handler at `vector`, with interrupts
+    /// disabled until it returns.
+    fn enter_interrupt(&mut self, vector: u16) -> Result<(), VmError> {
+        self.push(self.get_register(Registers::Flags) as u8)?;
+        self.push_word(self.pc())?;
+        self.set_flag(Flags::InterruptEnable, false);
+        self.set_register(Registers::PC, vector);
+        Ok(())
+    }
+
+    fn stack_fault(&self, fault: StackFault) -> VmError {
+        VmError::Stack {
+            pc: 0,
+            opcode: 0,
+            sp: self.sp(),
+            fault,
+        }
+    }
+
+    /// Moves SP `size` bytes down, returning the new SP.
+    fn grow_stack(&mut self, size: u16) -> Result<u16, VmError> {
+        let sp = self.sp();
+        if sp > self.stack_base {
+            return Err(self.stack_fault(StackFault::Underflow));
+        }
+        if sp < self.stack_limit || sp - self.stack_limit < size {
+            return Err(self.stack_fault(StackFault::Overflow));
+        }
+        let sp = sp - size;
+        self.set_register(Registers::SP, sp);
+        self.max_stack_depth = self.max_stack_depth.max(self.stack_depth());
+        Ok(sp)
+    }
+
+    fn push(&mut self, v: u8) -> Result<(), VmError> {
+        let sp = self.grow_stack(1)?;
+        // println!("sp = {sp}");
+        self.write(sp, v)
+    }
+
+    fn pop(&mut self) -> Result<u8, VmError> {
+        let sp = self.sp();
+        if sp >= self.stack_base {
+            return Err(self.stack_fault(StackFault::Underflow));
+        }
+        // SP was moved below the stack (say `Leave` with a bad BP), it isn't popping the stack
+        if sp < self.stack_limit {
+            return Err(self.stack_fault(StackFault::Overflow));
+        }
+        let v = self.read(sp)?;
+        self.set_register(Registers::SP, sp + 1);
+        Ok(v)
+    }
+
+    /// The word ends up low byte first in memory, like the high byte was pushed first.
+    fn push_word(&mut self, v: u16) -> Result<(), VmError> {
+        let sp = self.grow_stack(2)?;
+        self.write_word(sp, v)
+    }
+
+    fn pop_word(&mut self) -> Result<u16, VmError> {
+        let low = self.pop()? as u16;
+        let high = self.pop()? as u16;
+        Ok(high << 8 | low)
+    }
+
+    fn fetch(&mut self) -> Result<u8, VmError> {
+        let pc = self.pc();
+        let opcode = self.bus.fetch(pc)?;
+        self.tracer.trace(&TraceEvent::Fetch {
+            addr: pc,
+            byte: opcode,
+        });
+        self.watched(pc, Access::Execute, Some(opcode), opcode);
+        self.set_register(Registers::PC, pc.wrapping_add(1));
         Ok(opcode)
     }
 
+    /// Every data read of the program goes through here, so the watchpoints see it.
+    fn read(&mut self, addr: u16) -> Result<u8, VmError> {
+        let value = self.bus.read(addr)?;
+        self.watched(addr, Access::Read, Some(value), value);
+        Ok(value)
+    }
+
+    /// Every write of the program goes through here, so the watchpoints, the journal and the
+    /// tracer see it.
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), VmError> {
+        let watched = self.watchpoints.watches(addr, Access::Write);
+        let old = if watched || self.journal.is_some() {
+            self.bus.peek(addr)
+        } else {
+            None
+        };
+        self.bus.write(addr, value)?;
+        self.tracer.trace(&TraceEvent::Memory { addr, value });
+        if let Some(journal) = &mut self.journal {
+            journal.written(addr, old, self.bus.is_mapped(addr));
+        }
+        self.watched(addr, Access::Write, old, value);
+        Ok(())
+    }
+
+    /// Writes `value` low byte first, either both bytes or (when the step faults) neither:
+    /// the protection of both is checked before writing, and the low byte is put back if the
+    /// bus refuses the high one.
+    fn write_word(&mut self, addr: u16, value: u16) -> Result<(), VmError> {
+        let high = addr.wrapping_add(1);
+        self.bus.protection.check(addr, Access::Write)?;
+        self.bus.protection.check(high, Access::Write)?;
+        let old = self.bus.peek(addr);
+        self.write(addr, value as u8)?;
+        if let Err(err) = self.write(high, (value >> 8) as u8) {
+            if let Some(old) = old {
+                let _ = self.bus.poke(addr, old);
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn watched(&mut self, addr: u16, access: Access, old: Option<u8>, new: u8) {
+        if self.watchpoints.watches(addr, access) {
+            self.watchpoints.hit(WatchHit {
+                pc: self.instruction,
+                addr,
+                access,
+                old,
+                new,
+            });
+        }
+    }
+
     fn set_flag(&mut self, flag: Flags, condition: bool) {
         // println!("Registers::Flags = {}", self.get_register(Registers::Flags));
 
         if condition {
             self.set_register(
                 Registers::Flags,
-                self.get_register(Registers::Flags) | flag as u8,
+                self.get_register(Registers::Flags) | flag as u16,
             );
         } else {
             self.set_register(
                 Registers::Flags,
-                self.get_register(Registers::Flags) & !(flag as u8),
+                self.get_register(Registers::Flags) & !(flag as u16),
             );
         }
     }
@@ -220,87 +782,24 @@ impl Machine {
     pub fn clear_flag(&mut self, flag: Flags) {
         self.set_register(
             Registers::Flags,
-            self.get_register(Registers::Flags) & !(flag as u8),
+            self.get_register(Registers::Flags) & !(flag as u16),
         );
     }
 
     fn is_flag_set(&self, flag: Flags) -> bool {
-        self.get_register(Registers::Flags) & flag as u8 != 0
-    }
-
-    fn decode(&mut self, opcode: u8) -> Result<Instruction> {
-        let args = opcode & 0x0F;
-        match opcode >> 4 {
-            0x0 => Ok(Instruction::Nop),
-            0x1 => {
-                let value = self.fetch()?;
-                Ok(Instruction::Push(value))
-            }
-            0x2 => match Registers::from_u8_custom(args) {
-                Some(reg) => Ok(Instruction::PopRegister(reg)),
-                None => Err(anyhow::anyhow!("Invalid register code: {}", args)),
-            },
-            0x3 => match Registers::from_u8_custom(args) {
-                Some(reg) => Ok(Instruction::PushRegister(reg)),
-                None => Err(anyhow::anyhow!("Invalid register code: {}", args)),
-            },
-            0x4 => Ok(Instruction::AddStack),
-            // LoadImmediate(Register, value)
-            // 0110 rrrr | iiiiiiii
-            0x5 => {
-                let reg = Registers::from_u8_custom(args)
-                    .ok_or(anyhow::anyhow!("Invalid register code: {}", args))?;
-                let value = self.fetch()?;
-                Ok(Instruction::LoadImmediate(reg, value))
-            }
-            // LoadMemory(Register, address)
-            // 0111 rrrr | aaaaaaaa | aaaaaaaa
-            0x6 => {
-                let reg = Registers::from_u8_custom(args)
-                    .ok_or(anyhow::anyhow!("Invalid register code: {}", args))?;
-                let value = (self.fetch()? as u16) << 8 | self.fetch()? as u16;
-
-                // println!("{:02X}", value);
-                Ok(Instruction::LoadMemory(reg, value))
-            }
-            // Store(Registers, u16),
-            // 1000 rrrr | aaaaaaaa | aaaaaaaa
-            0x7 => {
-                let reg = Registers::from_u8_custom(args)
-                    .ok_or(anyhow::anyhow!("Invalid register code: {}", args))?;
-                let value = (self.fetch()? as u16) << 8 | self.fetch()? as u16;
-
-                Ok(Instruction::Store(reg, value))
-            }
-            0x8 => {
-                let operation = ALUOperation::from_u8_custom(args)
-                    .ok_or(anyhow::anyhow!("Invalid operation code: {}", args))?;
-
-                let next = self.fetch()?;
-                let reg1 = Registers::from_u8_custom(next >> 4)
-                    .ok_or(anyhow::anyhow!("Invalid register code: {}", next >> 4))?;
-
-                let reg2 = Registers::from_u8_custom(next & 0xF)
-                    .ok_or(anyhow::anyhow!("Invalid register code: {}", next & 0xF))?;
-
-                Ok(Instruction::ALU(operation, reg1, reg2))
-            }
-            0x9 => {
-                let address = (self.fetch()? as u16) << 8 | self.fetch()? as u16;
-                Ok(Instruction::Jump(JumpTarget::Address(address)))
-            }
-            0xA => {
-                let condition = JumpCondition::from_u8_custom(args)
-                    .ok_or(anyhow::anyhow!("Invalid condition code: {}", args))?;
-
-                let address = (self.fetch()? as u16) << 8 | self.fetch()? as u16;
-                Ok(Instruction::JumpConditional(
-                    condition,
-                    JumpTarget::Address(address),
-                ))
-            }
-            0xF => Ok(Instruction::Interrupt(args)),
-            _ => Err(anyhow::anyhow!("Unknown opcode: {:X}", opcode)),
-        }
+        self.get_register(Registers::Flags) & flag as u16 != 0
+    }
+
+    fn decode(&mut self, opcode: u8) -> Result<Instruction, VmError> {
+        Instruction::decode_with(opcode, || self.fetch())
+    }
+}
+
+/// The address a jump goes to. Only the assembler deals in labels, an instruction the
+/// machine is handed with one can't run.
+fn target(address: JumpTarget) -> Result<u16, VmError> {
+    match address {
+        JumpTarget::Address(addr) => Ok(addr),
+        JumpTarget::Label(_) => Err(DECODE_FAULT),
     }
 }
@@ -0,0 +1,201 @@
+//! Reverse execution.
+//!
+//! While the journal is on every step records what it is about to change: the registers
+//! and the bytes it overwrites. Steps are grouped in segments starting with a checkpoint (a
+//! snapshot), and only the last few segments are kept, so the journal never grows past a
+//! known size. Stepping back undoes one step; arriving at the start of a segment restores
+//! its checkpoint, which also brings back the state of devices the steps can't undo.
+//!
+//! Checkpoints and old values are read with `Bus::peek`, so the journal needs a bus that has
+//! it. A step that wrote memory the bus can't peek can't be stepped back over.
+
+use std::collections::VecDeque;
+
+use crate::{error::VmError, irq::IrqController, registers::Registers, Machine};
+
+/// Steps recorded after each checkpoint.
+pub const CHECKPOINT_INTERVAL: usize = 256;
+
+/// What one step changed, enough to undo it.
+struct Delta {
+    registers: [u16; 8],
+    irq: IrqController,
+    max_stack_depth: u16,
+    /// Address and old value of every byte written, in order.
+    writes: Vec<(u16, u8)>,
+    /// Memory written without knowing what was there, the step can't be undone.
+    unknown: Option<u16>,
+}
+
+struct Segment {
+    checkpoint: Vec<u8>,
+    deltas: Vec<Delta>,
+}
+
+pub(crate) struct Journal {
+    segments: VecDeque<Segment>,
+    max_segments: usize,
+    /// Bytes the current step overwrote so far.
+    writes: Vec<(u16, u8)>,
+    unknown: Option<u16>,
+}
+
+impl Journal {
+    pub(crate) fn clear(&mut self) {
+        self.segments.clear();
+        self.writes.clear();
+        self.unknown = None;
+    }
+
+    /// Records a write of `addr` whose old value was `old`. Devices that can't peek are put
+    /// back by the next checkpoint, memory has to be known.
+    pub(crate) fn written(&mut self, addr: u16, old: Option<u8>, device: bool) {
+        match old {
+            Some(old) => self.writes.push((addr, old)),
+            None if !device => {
+                self.unknown.get_or_insert(addr);
+            }
+            None => {}
+        }
+    }
+}
+
+impl Machine {
+    /// Starts recording steps so they can be undone, keeping at least the last `steps`.
+    ///
+    /// Fails like `snapshot` when the bus can't peek, the journal couldn't take checkpoints.
+    pub fn start_journal(&mut self, steps: usize) -> Result<(), VmError> {
+        self.snapshot()?;
+        self.journal = Some(Journal {
+            segments: VecDeque::new(),
+            max_segments: steps.div_ceil(CHECKPOINT_INTERVAL) + 1,
+            writes: Vec::new(),
+            unknown: None,
+        });
+        Ok(())
+    }
+
+    /// Stops recording and forgets the history.
+    pub fn stop_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// How many steps `step_back` can undo.
+    pub fn journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, |journal| {
+            journal
+                .segments
+                .iter()
+                .map(|segment| segment.deltas.len())
+                .sum()
+        })
+    }
+
+    /// Called at the start of a step, opens a new segment when the last one is full.
+    pub(super) fn journal_begin(&mut self) -> Result<(), VmError> {
+        let full = match &self.journal {
+            None => return Ok(()),
+            Some(journal) => journal
+                .segments
+                .back()
+                .is_none_or(|segment| segment.deltas.len() >= CHECKPOINT_INTERVAL),
+        };
+        let checkpoint = if full { Some(self.snapshot()?) } else { None };
+        if let Some(journal) = &mut self.journal {
+            journal.writes.clear();
+            journal.unknown = None;
+            if let Some(checkpoint) = checkpoint {
+                journal.segments.push_back(Segment {
+                    checkpoint,
+                    deltas: Vec::new(),
+                });
+                while journal.segments.len() > journal.max_segments {
+                    journal.segments.pop_front();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Called once a step went through, with the state from before it.
+    pub(super) fn journal_commit(
+        &mut self,
+        registers: [u16; 8],
+        irq: IrqController,
+        max_stack_depth: u16,
+    ) {
+        if let Some(journal) = &mut self.journal {
+            let writes = std::mem::take(&mut journal.writes);
+            if let Some(segment) = journal.segments.back_mut() {
+                segment.deltas.push(Delta {
+                    registers,
+                    irq,
+                    max_stack_depth,
+                    writes,
+                    unknown: journal.unknown.take(),
+                });
+            }
+        }
+    }
+
+    /// Undoes the last step, false when there is no history left.
+    ///
+    /// Registers, memory and the machine's counters go back exactly. Devices only go back
+    /// at checkpoints, in between the writes to them are undone as writes (console output
+    /// stays printed).
+    pub fn step_back(&mut self) -> Result<bool, VmError> {
+        let Some(mut journal) = self.journal.take() else {
+            return Ok(false);
+        };
+        let result = self.undo(&mut journal);
+        self.journal = Some(journal);
+        result
+    }
+
+    /// Steps back until PC is `pc`, at least once. False when the history ran out first,
+    /// leaving the machine at the oldest state it has.
+    pub fn run_back_to(&mut self, pc: u16) -> Result<bool, VmError> {
+        while self.step_back()? {
+            if self.pc() == pc {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn undo(&mut self, journal: &mut Journal) -> Result<bool, VmError> {
+        while journal
+            .segments
+            .back()
+            .is_some_and(|segment| segment.deltas.is_empty())
+        {
+            journal.segments.pop_back();
+        }
+        let Some(segment) = journal.segments.back_mut() else {
+            return Ok(false);
+        };
+        let delta = segment.deltas.last().unwrap();
+        if let Some(addr) = delta.unknown {
+            return Err(VmError::Irreversible {
+                pc: delta.registers[Registers::PC as usize],
+                addr,
+            });
+        }
+        let delta = segment.deltas.pop().unwrap();
+        if segment.deltas.is_empty() {
+            // back at the checkpoint, it knows everything, devices included
+            let segment = journal.segments.pop_back().unwrap();
+            self.restore(&segment.checkpoint)?;
+            return Ok(true);
+        }
+        for (addr, old) in delta.writes.into_iter().rev() {
+            self.bus.poke(addr, old)?;
+        }
+        self.registers = delta.registers;
+        self.irq = delta.irq;
+        self.max_stack_depth = delta.max_stack_depth;
+        self.cycles -= 1;
+        self.halt = false;
+        Ok(true)
+    }
+}
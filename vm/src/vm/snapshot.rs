@@ -0,0 +1,233 @@
+//! The snapshot format, all numbers little endian:
+//!
+//! ```text
+//! "BMSS" version:u16
+//! registers:[u16; 8] halt:u8 cycles:u64
+//! stack_base:u16 stack_limit:u16 max_stack_depth:u16 ivt_base:u16
+//! irq_pending:u16 irq_masked:u16
+//! ranges:u32 { start:u16 length:u32 memory:[u8; length] }*
+//! devices:u16 { start:u16 length:u32 state:[u8; length] }*
+//! ```
+//!
+//! The ranges are the memory behind the devices the bus can `peek`, addresses it can't are
+//! taken as not backed and left out.
+
+use crate::{bus::Bus, error::VmError, irq::IrqController, registers::Registers, Machine};
+
+const MAGIC: &[u8; 4] = b"BMSS";
+/// Bumped whenever the layout changes, snapshots of other versions are refused.
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+fn snapshot_error(reason: impl Into<String>) -> VmError {
+    VmError::Snapshot {
+        reason: reason.into(),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], VmError> {
+        if self.bytes.len() < size {
+            return Err(snapshot_error("snapshot is truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, VmError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, VmError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, VmError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, VmError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+impl Machine {
+    /// Captures everything the program can see or change: registers, halt state, stack and
+    /// interrupt setup, pending IRQs, the whole memory behind the devices and the state of
+    /// every mapped device.
+    ///
+    /// What the host set up is left out: interrupt handlers, memory protection, watchpoints
+    /// and the devices themselves, which `restore` expects to be mapped already.
+    ///
+    /// Memory is read with `Bus::peek`, so taking a snapshot has no side effects. Fails when
+    /// the bus can't peek at all.
+    pub fn snapshot(&self) -> Result<Vec<u8>, VmError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(SNAPSHOT_VERSION.to_le_bytes());
+        for register in self.registers {
+            bytes.extend(register.to_le_bytes());
+        }
+        bytes.push(self.halt as u8);
+        bytes.extend(self.cycles.to_le_bytes());
+        for value in [
+            self.stack_base,
+            self.stack_limit,
+            self.max_stack_depth,
+            self.ivt_base,
+            self.irq.pending,
+            self.irq.masked,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+
+        let ranges = memory_ranges(self.bus.memory());
+        if ranges.is_empty() {
+            return Err(snapshot_error(
+                "the bus can't be read without side effects, it has no peek",
+            ));
+        }
+        bytes.extend((ranges.len() as u32).to_le_bytes());
+        for (start, memory) in ranges {
+            bytes.extend(start.to_le_bytes());
+            bytes.extend((memory.len() as u32).to_le_bytes());
+            bytes.extend(memory);
+        }
+
+        let devices = self.bus.save_devices();
+        bytes.extend((devices.len() as u16).to_le_bytes());
+        for (start, state) in devices {
+            bytes.extend(start.to_le_bytes());
+            bytes.extend((state.len() as u32).to_le_bytes());
+            bytes.extend(state);
+        }
+        Ok(bytes)
+    }
+
+    /// Puts the machine back in the state `snapshot` captured.
+    ///
+    /// The same devices have to be mapped at the same addresses as when it was taken. The
+    /// machine is left alone when the snapshot doesn't fit it, or when the bus faults while
+    /// it is restored. Only the bytes that differ from the current memory are written.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), VmError> {
+        let mut reader = Reader { bytes: snapshot };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(snapshot_error("not a byte machine snapshot"));
+        }
+        let version = reader.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(snapshot_error(format!(
+                "snapshot version {version}, this machine reads version {SNAPSHOT_VERSION}"
+            )));
+        }
+
+        let mut registers = [0; 8];
+        for (index, value) in registers.iter_mut().enumerate() {
+            *value = reader.u16()?;
+            let register = Registers::from_u8_custom(index as u8).unwrap();
+            if *value & !register.mask() != 0 {
+                return Err(snapshot_error(format!(
+                    "{register} holds 0x{value:04X}, more bits than it has"
+                )));
+            }
+        }
+        let halt = reader.u8()? != 0;
+        let cycles = reader.u64()?;
+        let [stack_base, stack_limit, max_stack_depth, ivt_base, pending, masked] = [
+            reader.u16()?,
+            reader.u16()?,
+            reader.u16()?,
+            reader.u16()?,
+            reader.u16()?,
+            reader.u16()?,
+        ];
+        let mut ranges = Vec::new();
+        for _ in 0..reader.u32()? {
+            let start = reader.u16()?;
+            let size = reader.u32()? as usize;
+            if start as usize + size > 0x10000 {
+                return Err(snapshot_error("memory range past the end of memory"));
+            }
+            ranges.push((start, reader.take(size)?));
+        }
+
+        let mut devices = Vec::new();
+        for _ in 0..reader.u16()? {
+            let start = reader.u16()?;
+            let size = reader.u32()? as usize;
+            devices.push((start, reader.take(size)?));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(snapshot_error("trailing bytes after the snapshot"));
+        }
+        let saved = self.bus.save_devices();
+        self.bus.restore_devices(&devices)?;
+        if let Err(err) = self.restore_memory(&ranges) {
+            let saved: Vec<(u16, &[u8])> = saved
+                .iter()
+                .map(|(start, state)| (*start, state.as_slice()))
+                .collect();
+            let _ = self.bus.restore_devices(&saved);
+            return Err(err);
+        }
+        self.registers = registers;
+        self.halt = halt;
+        self.cycles = cycles;
+        self.stack_base = stack_base;
+        self.stack_limit = stack_limit;
+        self.max_stack_depth = max_stack_depth;
+        self.ivt_base = ivt_base;
+        self.irq = IrqController { pending, masked };
+        // the history led somewhere else
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+        }
+        Ok(())
+    }
+
+    /// Writes the bytes of `ranges` that differ from memory, putting back the ones already
+    /// written if the bus faults.
+    fn restore_memory(&mut self, ranges: &[(u16, &[u8])]) -> Result<(), VmError> {
+        let bus = self.bus.memory_mut();
+        let mut written = Vec::new();
+        for (start, memory) in ranges {
+            for (offset, byte) in memory.iter().enumerate() {
+                let addr = start + offset as u16;
+                let old = bus.peek(addr);
+                if old == Some(*byte) {
+                    continue;
+                }
+                if let Err(err) = bus.write(addr, *byte) {
+                    for (addr, old) in written.into_iter().rev() {
+                        let _ = bus.write(addr, old);
+                    }
+                    return Err(err);
+                }
+                if let Some(old) = old {
+                    written.push((addr, old));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The runs of addresses `bus` can peek, with their bytes.
+fn memory_ranges(bus: &dyn Bus) -> Vec<(u16, Vec<u8>)> {
+    let mut ranges: Vec<(u16, Vec<u8>)> = Vec::new();
+    let mut open = false;
+    for addr in 0..=u16::MAX {
+        match bus.peek(addr) {
+            Some(byte) if open => ranges.last_mut().unwrap().1.push(byte),
+            Some(byte) => {
+                ranges.push((addr, vec![byte]));
+                open = true;
+            }
+            None => open = false,
+        }
+    }
+    ranges
+}
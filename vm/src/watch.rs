@@ -0,0 +1,94 @@
+use crate::error::Access;
+
+/// An access that hit a watchpoint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the instruction making the access.
+    pub pc: u16,
+    pub addr: u16,
+    pub access: Access,
+    /// The byte before the access, `None` when the bus couldn't tell without side effects.
+    pub old: Option<u8>,
+    /// The byte after the access, the one read for reads and fetches.
+    pub new: u8,
+}
+
+/// What happens when a watchpoint is hit.
+pub enum WatchAction {
+    /// `Machine::step` finishes the instruction and returns `VmError::Watchpoint`.
+    Stop,
+    /// The callback runs and execution carries on.
+    Call(Box<dyn FnMut(&WatchHit)>),
+}
+
+/// Handed out by `Machine::watch`, for removing the watchpoint again.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchId(usize);
+
+pub(crate) struct Watchpoint {
+    id: WatchId,
+    start: u16,
+    end: u16,
+    access: Access,
+    action: WatchAction,
+}
+
+/// The watchpoints of a machine.
+#[derive(Default)]
+pub(crate) struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+    /// The first stopping watchpoint hit by the current instruction.
+    pub(crate) stop: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    pub(crate) fn add(
+        &mut self,
+        start: u16,
+        size: u16,
+        access: Access,
+        action: WatchAction,
+    ) -> WatchId {
+        let id = WatchId(self.next_id);
+        self.next_id += 1;
+        self.watchpoints.push(Watchpoint {
+            id,
+            start,
+            end: start.saturating_add(size.max(1) - 1),
+            access,
+            action,
+        });
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: WatchId) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        self.watchpoints.len() != count
+    }
+
+    /// Whether an `access` of `addr` would hit anything, so the old value is worth reading.
+    pub(crate) fn watches(&self, addr: u16, access: Access) -> bool {
+        self.watchpoints.iter().any(|watchpoint| {
+            watchpoint.access == access && watchpoint.start <= addr && addr <= watchpoint.end
+        })
+    }
+
+    pub(crate) fn hit(&mut self, hit: WatchHit) {
+        for watchpoint in &mut self.watchpoints {
+            if watchpoint.access != hit.access
+                || hit.addr < watchpoint.start
+                || watchpoint.end < hit.addr
+            {
+                continue;
+            }
+            match &mut watchpoint.action {
+                WatchAction::Stop => {
+                    self.stop.get_or_insert(hit);
+                }
+                WatchAction::Call(callback) => callback(&hit),
+            }
+        }
+    }
+}
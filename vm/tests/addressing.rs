@@ -0,0 +1,112 @@
+use vm::{assembler::assemble, Machine, Registers};
+
+/// Runs the single instruction `source` with memory at 0x1000 counting up from 0x50, after
+/// `setup` set its registers.
+fn run(source: &str, setup: &[(Registers, u16)]) -> Machine {
+    let mut vm = Machine::new();
+    vm.bus.load(&assemble(source).unwrap()).unwrap();
+    for offset in 0..8 {
+        vm.bus.write(0x1000 + offset, 0x50 + offset as u8).unwrap();
+    }
+    for (register, value) in setup {
+        vm.set_register(*register, *value);
+    }
+    vm.step().unwrap();
+    vm
+}
+
+fn word(vm: &Machine, addr: u16) -> u16 {
+    vm.bus.peek(addr).unwrap() as u16 | (vm.bus.peek(addr + 1).unwrap() as u16) << 8
+}
+
+#[test]
+fn pair() {
+    let vm = run("LoadMemory C [A:B]", &[(Registers::AB, 0x1002)]);
+    assert_eq!(vm.get_register(Registers::C), 0x52);
+    assert_eq!(vm.get_register(Registers::AB), 0x1002);
+
+    let vm = run("LoadMemory BP [C:D]", &[(Registers::CD, 0x1002)]);
+    assert_eq!(vm.get_register(Registers::BP), 0x5352);
+
+    let setup = [(Registers::AB, 0x2000), (Registers::D, 0x99)];
+    let vm = run("Store D [A:B]", &setup);
+    assert_eq!(vm.bus.peek(0x2000), Some(0x99));
+    assert_eq!(vm.get_register(Registers::AB), 0x2000);
+}
+
+#[test]
+fn pair_post_increment() {
+    let vm = run("LoadMemory C [A:B]+", &[(Registers::AB, 0x1001)]);
+    assert_eq!(vm.get_register(Registers::C), 0x51);
+    assert_eq!(vm.get_register(Registers::AB), 0x1002);
+
+    // the increment carries into the high register
+    let vm = run("LoadMemory C [A:B]+", &[(Registers::AB, 0x10FF)]);
+    assert_eq!(vm.get_register(Registers::AB), 0x1100);
+
+    let vm = run("LoadMemory BP [A:B]+", &[(Registers::AB, 0x1000)]);
+    assert_eq!(vm.get_register(Registers::BP), 0x5150);
+    assert_eq!(vm.get_register(Registers::AB), 0x1002);
+
+    let setup = [(Registers::CD, 0x2000), (Registers::BP, 0xBEEF)];
+    let vm = run("Store BP [C:D]+", &setup);
+    assert_eq!(word(&vm, 0x2000), 0xBEEF);
+    assert_eq!(vm.get_register(Registers::CD), 0x2002);
+}
+
+#[test]
+fn offset() {
+    let vm = run("LoadMemory A [BP+3]", &[(Registers::BP, 0x1002)]);
+    assert_eq!(vm.get_register(Registers::A), 0x55);
+    assert_eq!(vm.get_register(Registers::BP), 0x1002);
+
+    let vm = run("LoadMemory SP [BP-2]", &[(Registers::BP, 0x1002)]);
+    assert_eq!(vm.get_register(Registers::SP), 0x5150);
+
+    let setup = [(Registers::BP, 0x2000), (Registers::B, 0x77)];
+    let vm = run("Store B [BP-1]", &setup);
+    assert_eq!(vm.bus.peek(0x1FFF), Some(0x77));
+    assert_eq!(vm.get_register(Registers::BP), 0x2000);
+}
+
+#[test]
+fn post_increment() {
+    let vm = run("LoadMemory A [BP]+", &[(Registers::BP, 0x1003)]);
+    assert_eq!(vm.get_register(Registers::A), 0x53);
+    assert_eq!(vm.get_register(Registers::BP), 0x1004);
+
+    let vm = run("LoadMemory SP [BP]+", &[(Registers::BP, 0x1003)]);
+    assert_eq!(vm.get_register(Registers::SP), 0x5453);
+    assert_eq!(vm.get_register(Registers::BP), 0x1005);
+
+    let setup = [(Registers::BP, 0x2000), (Registers::A, 0x42)];
+    let vm = run("Store A [BP]+", &setup);
+    assert_eq!(vm.bus.peek(0x2000), Some(0x42));
+    assert_eq!(vm.get_register(Registers::BP), 0x2001);
+}
+
+#[test]
+fn load_into_the_base_keeps_the_loaded_value() {
+    let vm = run("LoadMemory A [A:B]+", &[(Registers::AB, 0x1000)]);
+    assert_eq!(vm.get_register(Registers::AB), 0x5000);
+
+    let vm = run("LoadMemory B [A:B]+", &[(Registers::AB, 0x1001)]);
+    assert_eq!(vm.get_register(Registers::AB), 0x1051);
+
+    let vm = run("LoadMemory BP [BP]+", &[(Registers::BP, 0x1000)]);
+    assert_eq!(vm.get_register(Registers::BP), 0x5150);
+
+    let vm = run("LoadMemory BP [BP+2]", &[(Registers::BP, 0x1000)]);
+    assert_eq!(vm.get_register(Registers::BP), 0x5352);
+}
+
+#[test]
+fn store_of_the_base_stores_the_address_before_it_moves() {
+    let vm = run("Store B [A:B]+", &[(Registers::AB, 0x2010)]);
+    assert_eq!(vm.bus.peek(0x2010), Some(0x10));
+    assert_eq!(vm.get_register(Registers::AB), 0x2011);
+
+    let vm = run("Store BP [BP]+", &[(Registers::BP, 0x2000)]);
+    assert_eq!(word(&vm, 0x2000), 0x2000);
+    assert_eq!(vm.get_register(Registers::BP), 0x2002);
+}
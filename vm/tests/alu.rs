@@ -0,0 +1,149 @@
+use vm::{ALUOperation, Instruction, JumpCondition, JumpTarget, Machine, Registers};
+
+const C: u16 = 1 << 0;
+const Z: u16 = 1 << 1;
+const N: u16 = 1 << 2;
+const V: u16 = 1 << 3;
+
+/// Runs `operation` on `a` and `b` with Carry set to `carry`, 8 bits wide in A and B or 16
+/// bits wide in C:D and A:B. Gives the destination register and the flags afterwards.
+fn alu(operation: ALUOperation, a: u16, b: u16, wide: bool, carry: bool) -> (u16, u16) {
+    let (dst, src) = if wide {
+        (Registers::CD, Registers::AB)
+    } else {
+        (Registers::A, Registers::B)
+    };
+    let mut vm = Machine::new();
+    vm.bus
+        .load(&Instruction::ALU(operation, dst, src).encode().unwrap())
+        .unwrap();
+    vm.set_register(dst, a);
+    vm.set_register(src, b);
+    vm.set_register(Registers::Flags, carry as u16);
+    vm.step().unwrap();
+    (vm.get_register(dst), vm.get_register(Registers::Flags))
+}
+
+#[test]
+fn add_and_adc_flags() {
+    use ALUOperation::*;
+    assert_eq!(alu(Add, 0x7F, 1, false, false), (0x80, N | V));
+    assert_eq!(alu(Add, 0xFF, 1, false, false), (0x00, C | Z));
+    assert_eq!(alu(Add, 0x80, 0x80, false, false), (0x00, C | Z | V));
+    assert_eq!(alu(Add, 0x01, 0x01, false, true), (0x02, 0));
+    assert_eq!(alu(Adc, 0x7F, 0, false, true), (0x80, N | V));
+    assert_eq!(alu(Adc, 0xFF, 0, false, true), (0x00, C | Z));
+
+    assert_eq!(alu(Add, 0x7FFF, 1, true, false), (0x8000, N | V));
+    assert_eq!(alu(Add, 0xFFFF, 1, true, false), (0x0000, C | Z));
+    assert_eq!(alu(Add, 0x00FF, 1, true, false), (0x0100, 0));
+    assert_eq!(alu(Adc, 0xFFFE, 1, true, true), (0x0000, C | Z));
+}
+
+#[test]
+fn sub_sbc_and_cmp_flags() {
+    use ALUOperation::*;
+    assert_eq!(alu(Sub, 0x80, 1, false, false), (0x7F, V));
+    assert_eq!(alu(Sub, 0, 1, false, false), (0xFF, C | N));
+    assert_eq!(alu(Sub, 5, 5, false, false), (0x00, Z));
+    assert_eq!(alu(Sbc, 5, 5, false, true), (0xFF, C | N));
+    assert_eq!(alu(Sbc, 5, 4, false, true), (0x00, Z));
+    assert_eq!(alu(Sbc, 0x80, 0, false, true), (0x7F, V));
+    assert_eq!(alu(Sbc, 0, 0xFF, false, true), (0x00, C | Z));
+    // Cmp keeps the flags of the subtraction but not its result
+    assert_eq!(alu(Cmp, 0, 1, false, false), (0x00, C | N));
+    assert_eq!(alu(Cmp, 0x80, 1, false, false), (0x80, V));
+
+    assert_eq!(alu(Sub, 0x8000, 1, true, false), (0x7FFF, V));
+    assert_eq!(alu(Sub, 0, 1, true, false), (0xFFFF, C | N));
+    assert_eq!(alu(Sub, 0x0100, 1, true, false), (0x00FF, 0));
+    assert_eq!(alu(Sbc, 0, 0, true, true), (0xFFFF, C | N));
+    assert_eq!(alu(Sbc, 0x8000, 0, true, true), (0x7FFF, V));
+}
+
+#[test]
+fn shift_and_rotate_flags() {
+    use ALUOperation::*;
+    assert_eq!(alu(Shl, 0x81, 1, false, false), (0x02, C));
+    assert_eq!(alu(Shr, 0x81, 1, false, false), (0x40, C));
+    assert_eq!(alu(Shl, 0x81, 8, false, false), (0x00, C | Z));
+    assert_eq!(alu(Shl, 0x81, 0, false, true), (0x81, C | N));
+
+    // a rotate through Carry is 9 bits wide, by 9 it comes back around
+    assert_eq!(alu(Rcl, 0x81, 0, false, false), (0x81, N));
+    assert_eq!(alu(Rcl, 0x81, 0, false, true), (0x81, C | N));
+    assert_eq!(alu(Rcl, 0x81, 1, false, false), (0x02, C));
+    assert_eq!(alu(Rcl, 0x81, 8, false, false), (0x40, C));
+    assert_eq!(alu(Rcl, 0x81, 8, false, true), (0xC0, C | N));
+    assert_eq!(alu(Rcl, 0x81, 9, false, false), (0x81, N));
+    assert_eq!(alu(Rcl, 0x81, 9, false, true), (0x81, C | N));
+    assert_eq!(alu(Rcr, 0x81, 0, false, false), (0x81, N));
+    assert_eq!(alu(Rcr, 0x81, 1, false, true), (0xC0, C | N));
+    assert_eq!(alu(Rcr, 0x81, 8, false, false), (0x02, C));
+    assert_eq!(alu(Rcr, 0x81, 8, false, true), (0x03, C));
+    assert_eq!(alu(Rcr, 0x81, 9, false, false), (0x81, N));
+    assert_eq!(alu(Rcr, 0x00, 9, false, false), (0x00, Z));
+
+    assert_eq!(alu(Rcl, 0x8001, 1, true, false), (0x0002, C));
+    assert_eq!(alu(Rcl, 0x8001, 17, true, false), (0x8001, N));
+    assert_eq!(alu(Rcr, 0x8001, 16, true, false), (0x0002, C));
+}
+
+#[test]
+fn logic_mul_and_div_flags() {
+    use ALUOperation::*;
+    assert_eq!(alu(And, 0xF0, 0x0F, false, true), (0x00, Z));
+    assert_eq!(alu(Or, 0x80, 0x01, false, true), (0x81, N));
+    assert_eq!(alu(Xor, 0xFF, 0xFF, false, false), (0x00, Z));
+    assert_eq!(alu(Not, 0x0F, 0, false, false), (0xF0, N));
+    assert_eq!(alu(Mul, 0x10, 0x10, false, false), (0x00, C | Z | V));
+    assert_eq!(alu(Mul, 0xFF, 0xFF, false, false), (0x01, C));
+    assert_eq!(alu(Div, 7, 2, false, true), (3, 0));
+    assert_eq!(alu(Mod, 7, 2, false, true), (1, 0));
+}
+
+#[test]
+fn jump_conditions() {
+    use JumpCondition::*;
+    let pairs: [(u8, u8); 7] = [
+        (1, 2),
+        (2, 2),
+        (3, 2),
+        (0xFF, 0x01),
+        (0x01, 0xFF),
+        (0x80, 0x7F),
+        (0x7F, 0x80),
+    ];
+    for (a, b) in pairs {
+        let (signed_a, signed_b) = (a as i8, b as i8);
+        for (condition, expected) in [
+            (EQ, a == b),
+            (NEQ, a != b),
+            (LT, a < b),
+            (GT, a > b),
+            (GE, a >= b),
+            (LE, a <= b),
+            (SLT, signed_a < signed_b),
+            (SGT, signed_a > signed_b),
+            (SGE, signed_a >= signed_b),
+            (SLE, signed_a <= signed_b),
+        ] {
+            let mut program = Instruction::ALU(ALUOperation::Cmp, Registers::A, Registers::B)
+                .encode()
+                .unwrap();
+            program.extend(
+                Instruction::JumpConditional(condition, JumpTarget::Address(0x0100))
+                    .encode()
+                    .unwrap(),
+            );
+            let mut vm = Machine::new();
+            vm.bus.load(&program).unwrap();
+            vm.set_register(Registers::A, a as u16);
+            vm.set_register(Registers::B, b as u16);
+            vm.step().unwrap();
+            vm.step().unwrap();
+            let jumped = vm.pc() == 0x0100;
+            assert_eq!(jumped, expected, "{a:#04X} {condition:?} {b:#04X}");
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    env, fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+const PROGRAM: &str = "
+    LoadImmediate A 1
+    Call double
+    Call double
+spin:
+    Jump spin
+double:
+    ALU Add A A
+    Ret
+";
+
+/// Runs the debugger on `PROGRAM` with `commands` on stdin, giving what it printed.
+fn debug(name: &str, commands: &str) -> String {
+    let path = env::temp_dir().join(format!("bmdbg-{}-{name}.naked", std::process::id()));
+    fs::write(&path, PROGRAM).unwrap();
+    let mut debugger = Command::new(env!("CARGO_BIN_EXE_bmdbg"))
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    debugger
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = debugger.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn next_runs_a_call_until_it_returns() {
+    let output = debug("next", "step\nnext\nregs\nquit\n");
+    assert!(output.contains("=>  0x0002  B0 00 0B     Call 0x000B"));
+    assert!(output.contains("=>  0x0005  B0 00 0B     Call 0x000B"));
+    assert!(output.contains("A: 2 | B: 0 | C: 0 | D: 0 SP: 65535 | PC: 5 |"));
+}
+
+#[test]
+fn step_counts_and_an_empty_line_repeats() {
+    // LoadImmediate, Call, Add, then Ret, Call, Add
+    let output = debug("step", "step 3\n\nregs\nquit\n");
+    assert!(output.contains("A: 4 | B: 0 | C: 0 | D: 0 SP: 65533 | PC: 13 |"));
+}
+
+#[test]
+fn memory_dump() {
+    let output = debug("x", "poke 0x2000 1 0x02 255\nx 0x1FFF 5\nx 0 2\nquit\n");
+    assert!(output.contains("0x1FFF: 00 01 02 FF 00"));
+    assert!(output.contains("0x0000: 50 01"));
+}
+
+#[test]
+fn disassembly_starts_a_few_instructions_before_pc() {
+    // in the second call, the listing starts three instructions back at the call
+    let output = debug("disas", "break spin\nstep 6\ndisas 0 2\ndisas\nquit\n");
+    assert!(output.contains(
+        "(bmdbg)     0x0000  50 01        LoadImmediate A 1
+    0x0002  B0 00 0B     Call 0x000B
+(bmdbg)     0x0005  B0 00 0B     Call 0x000B
+spin:
+  * 0x0008  90 00 08     Jump 0x0008
+double:
+    0x000B  80 00        ALU Add A A
+=>  0x000D  C0           Ret
+"
+    ));
+}
+
+#[test]
+fn breakpoints_stop_continue_and_run_gives_up() {
+    let output = debug("run", "break spin\ncontinue\ndelete spin\nrun\nquit\n");
+    assert!(output.contains("breakpoint\nspin:\n=>* 0x0008  90 00 08     Jump 0x0008"));
+    assert!(output.contains("still running after 1000000 steps"));
+}
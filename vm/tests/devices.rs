@@ -0,0 +1,215 @@
+use std::{
+    io::{self, Cursor, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use vm::{
+    devices::{
+        console::{SharedBuffer, CONSOLE_DATA, CONSOLE_STATUS, INPUT_AVAILABLE},
+        timer::{TIMER_CONTROL, TIMER_COUNTER, TIMER_ENABLE, TIMER_PERIODIC, TIMER_RELOAD},
+        BankedMemory, Console, Timer,
+    },
+    Device, IrqController,
+};
+
+#[test]
+fn console_status_and_end_of_input() {
+    let output = SharedBuffer::new();
+    let mut console = Console::new(Cursor::new("hi"), output.clone());
+
+    assert_eq!(console.read(CONSOLE_STATUS), INPUT_AVAILABLE);
+    assert_eq!(console.read(CONSOLE_DATA), b'h');
+    assert_eq!(console.read(CONSOLE_STATUS), INPUT_AVAILABLE);
+    assert_eq!(console.read(CONSOLE_DATA), b'i');
+    assert_eq!(console.read(CONSOLE_STATUS), 0);
+    assert_eq!(console.read(CONSOLE_DATA), 0);
+
+    console.write(CONSOLE_DATA, b'x');
+    console.write(CONSOLE_STATUS, b'y');
+    assert_eq!(output.bytes(), b"x");
+}
+
+/// Input that blocks until the test sends it something, like a terminal.
+struct Typing(Receiver<Vec<u8>>);
+
+impl Read for Typing {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.recv() {
+            Ok(line) => {
+                buf[..line.len()].copy_from_slice(&line);
+                Ok(line.len())
+            }
+            Err(_) => Ok(0),
+        }
+    }
+}
+
+/// Reads the status until `done` likes it, failing after a second.
+fn status_until(console: &mut Console, done: impl Fn(u8) -> bool) {
+    let start = Instant::now();
+    while !done(console.read(CONSOLE_STATUS)) {
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "status never changed"
+        );
+        thread::yield_now();
+    }
+}
+
+#[test]
+fn threaded_console_status_doesnt_wait_for_input() {
+    let (typing, input) = mpsc::channel();
+    let mut console = Console::threaded(Typing(input), SharedBuffer::new());
+
+    // nothing typed yet, and reading the status doesn't wait for it
+    assert_eq!(console.read(CONSOLE_STATUS), 0);
+    assert_eq!(console.read(CONSOLE_DATA), 0);
+
+    typing.send(b"ok".to_vec()).unwrap();
+    status_until(&mut console, |status| status == INPUT_AVAILABLE);
+    assert_eq!(console.read(CONSOLE_DATA), b'o');
+    status_until(&mut console, |status| status == INPUT_AVAILABLE);
+    assert_eq!(console.read(CONSOLE_DATA), b'k');
+
+    drop(typing);
+    assert_eq!(console.read(CONSOLE_STATUS), 0);
+    assert_eq!(console.read(CONSOLE_DATA), 0);
+}
+
+/// A timer on line 2 counting down from `reload`, started with `control`.
+fn timer(reload: u16, control: u8) -> Timer {
+    let mut timer = Timer::new(2);
+    timer.write(TIMER_RELOAD, reload as u8);
+    timer.write(TIMER_RELOAD + 1, (reload >> 8) as u8);
+    timer.write(TIMER_CONTROL, control);
+    timer
+}
+
+fn counter(timer: &mut Timer) -> u16 {
+    timer.read(TIMER_COUNTER) as u16 | (timer.read(TIMER_COUNTER + 1) as u16) << 8
+}
+
+#[test]
+fn one_shot_timer_fires_once() {
+    let mut timer = timer(0x0103, TIMER_ENABLE);
+    let mut irq = IrqController::new();
+    assert_eq!(counter(&mut timer), 0x0103);
+    for _ in 0..0x0102 {
+        timer.tick(&mut irq);
+    }
+    assert_eq!(counter(&mut timer), 1);
+    assert!(!irq.is_pending(2));
+
+    timer.tick(&mut irq);
+    assert!(irq.is_pending(2));
+    assert_eq!(counter(&mut timer), 0);
+    assert_eq!(timer.read(TIMER_CONTROL), 0);
+
+    irq.lower(2);
+    for _ in 0..0x0200 {
+        timer.tick(&mut irq);
+    }
+    assert!(!irq.is_pending(2));
+}
+
+#[test]
+fn periodic_timer_reloads() {
+    let mut timer = timer(3, TIMER_ENABLE | TIMER_PERIODIC);
+    let mut irq = IrqController::new();
+    for _ in 0..3 {
+        assert!(!irq.is_pending(2));
+        timer.tick(&mut irq);
+    }
+    assert!(irq.is_pending(2));
+    assert_eq!(counter(&mut timer), 3);
+    assert_eq!(timer.read(TIMER_CONTROL), TIMER_ENABLE | TIMER_PERIODIC);
+
+    irq.lower(2);
+    timer.tick(&mut irq);
+    timer.tick(&mut irq);
+    assert!(!irq.is_pending(2));
+    timer.tick(&mut irq);
+    assert!(irq.is_pending(2));
+}
+
+#[test]
+fn timer_with_reload_0_never_fires() {
+    let mut timer = timer(0, TIMER_ENABLE | TIMER_PERIODIC);
+    let mut irq = IrqController::new();
+    for _ in 0..0x10000 {
+        timer.tick(&mut irq);
+    }
+    assert!(!irq.is_pending(2));
+    assert_eq!(counter(&mut timer), 0);
+}
+
+#[test]
+fn stopped_timer_keeps_its_count() {
+    let mut timer = timer(10, TIMER_ENABLE);
+    let mut irq = IrqController::new();
+    timer.tick(&mut irq);
+    timer.tick(&mut irq);
+    // rewriting the control port reloads the counter, even to stop it
+    timer.write(TIMER_CONTROL, 0);
+    assert_eq!(counter(&mut timer), 10);
+    timer.tick(&mut irq);
+    assert_eq!(counter(&mut timer), 10);
+    assert!(!irq.is_pending(2));
+}
+
+#[test]
+fn bank_switching() {
+    let mut banks = BankedMemory::new(3, 4);
+    banks.bank_mut(1)[0] = 0x11;
+    banks.bank_mut(2)[3] = 0x23;
+    assert_eq!(banks.size(), 5);
+    assert_eq!(banks.read(0), 0);
+
+    banks.write(4, 1);
+    assert_eq!(banks.read(4), 1);
+    assert_eq!(banks.read(0), 0x11);
+    banks.write(1, 0x12);
+
+    banks.write(4, 2);
+    assert_eq!(banks.read(3), 0x23);
+    assert_eq!(banks.peek(1), Some(0));
+    banks.write(4, 1);
+    assert_eq!(banks.peek(1), Some(0x12));
+}
+
+#[test]
+fn bank_select_wraps_around() {
+    let mut banks = BankedMemory::new(3, 4);
+    banks.bank_mut(1)[0] = 0x11;
+    banks.write(4, 7);
+    assert_eq!(banks.bank(), 1);
+    assert_eq!(banks.read(0), 0x11);
+    banks.write(4, 255);
+    assert_eq!(banks.read(4), 0);
+
+    // 256 banks, every select value is a bank of its own
+    let mut banks = BankedMemory::new(256, 1);
+    banks.write(1, 255);
+    assert_eq!(banks.bank(), 255);
+}
+
+#[test]
+#[should_panic(expected = "window of 0 bytes")]
+fn empty_window() {
+    BankedMemory::new(2, 0);
+}
+
+#[test]
+#[should_panic(expected = "window of 65535 bytes")]
+fn window_without_room_for_the_select_port() {
+    BankedMemory::new(2, 0xFFFF);
+}
+
+#[test]
+fn biggest_window() {
+    let banks = BankedMemory::new(1, 0xFFFE);
+    assert_eq!(banks.size(), 0xFFFF);
+    assert_eq!(banks.banks(), 1);
+}
@@ -0,0 +1,268 @@
+use std::{cell::RefCell, rc::Rc};
+
+use vm::{
+    assembler::{assemble, labels},
+    devices::{
+        timer::{TIMER_BASE, TIMER_SIZE},
+        Timer,
+    },
+    Machine, Registers, VmError,
+};
+
+/// A machine with `source` loaded, every `Interrupt`/IRQ line logging itself to the result.
+fn machine(source: &str) -> (Machine, Rc<RefCell<Vec<u8>>>) {
+    let mut vm = Machine::new();
+    vm.bus.load(&assemble(source).unwrap()).unwrap();
+    let log = Rc::new(RefCell::new(Vec::new()));
+    for signal in 0..16 {
+        let log = log.clone();
+        vm.define_interrupt(signal, move |_: &mut Machine| {
+            log.borrow_mut().push(signal);
+            Ok(())
+        });
+    }
+    (vm, log)
+}
+
+#[test]
+fn irq_goes_to_the_guest_vector() {
+    let (mut vm, log) = machine("Ei\nNop\nNop\nhandler:\nIret");
+    vm.bus.write(0xEFE0 + 2 * 4, 4).unwrap();
+    vm.step().unwrap();
+    vm.irq.raise(4);
+
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 4);
+    assert!(!vm.irq.is_pending(4));
+    assert_eq!(vm.get_register(Registers::Flags), 0);
+    assert!(log.borrow().is_empty());
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 2);
+}
+
+#[test]
+fn irq_without_a_vector_goes_to_the_host() {
+    let (mut vm, log) = machine("Ei\nNop");
+    vm.step().unwrap();
+    vm.irq.raise(6);
+
+    vm.step().unwrap();
+    assert_eq!(*log.borrow(), [6]);
+    // delivering it was the whole step
+    assert_eq!(vm.pc(), 2);
+    assert!(!vm.irq.is_pending(6));
+}
+
+#[test]
+fn lower_lines_go_first() {
+    let (mut vm, log) = machine("Ei\nNop\nNop\nNop");
+    vm.step().unwrap();
+    vm.irq.raise(9);
+    vm.irq.raise(3);
+    vm.irq.raise(5);
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    assert_eq!(*log.borrow(), [3, 5, 9]);
+    assert_eq!(vm.pc(), 2);
+}
+
+#[test]
+fn masked_and_disabled_lines_stay_pending() {
+    let (mut vm, log) = machine("Nop\nEi\nNop\nNop");
+    vm.irq.raise(2);
+    vm.irq.set_masked(2, true);
+
+    // interrupts are off
+    vm.step().unwrap();
+    // the line is masked
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 4);
+    assert!(vm.irq.is_pending(2));
+    assert!(log.borrow().is_empty());
+
+    vm.irq.set_masked(2, false);
+    vm.step().unwrap();
+    assert_eq!(*log.borrow(), [2]);
+    assert_eq!(vm.pc(), 4);
+}
+
+#[test]
+fn periodic_timer_interrupts_the_program() {
+    // counts loops in A, the guest handler counts ticks in B
+    let (mut vm, _) = machine(
+        "
+    LoadImmediate C:D tick
+    Store D 0xEFE0
+    Store C 0xEFE1
+    LoadImmediate A 10
+    Store A 0xE008
+    LoadImmediate A 3
+    Store A 0xE00A
+    LoadImmediate A 0
+    Ei
+busy:
+    AddI A 1
+    Jump busy
+tick:
+    AddI B 1
+    Iret
+",
+    );
+    vm.bus
+        .map(TIMER_BASE, TIMER_SIZE, Box::new(Timer::new(0)))
+        .unwrap();
+    for _ in 0..9 + 100 {
+        vm.step().unwrap();
+    }
+    // a tick every 10 steps from the one enabling it, each one 3 steps long
+    assert_eq!(vm.get_register(Registers::B), 10);
+    assert_eq!(vm.get_register(Registers::A), (100 - 10 * 3) / 2);
+}
+
+/// Flags bits the tests look at.
+const CARRY: u16 = 1 << 0;
+const ZERO: u16 = 1 << 1;
+const NEGATIVE: u16 = 1 << 2;
+const INTERRUPT_ENABLE: u16 = 1 << 4;
+
+const GUEST: &str = "
+    LoadImmediate C:D handler   ; vector 1 lives at 0xEFE2, low byte first
+    Store D 0xEFE2
+    Store C 0xEFE3
+    Ei
+    CmpI A 1                    ; 0 - 1 sets Carry and Negative
+    Interrupt 1
+    Interrupt 2
+    Di
+    Interrupt 1
+    Halt
+handler:
+    AddI B 0                    ; sets Zero
+    Iret
+";
+
+#[test]
+fn interrupt_with_a_vector_runs_the_guest_handler_and_iret_comes_back() {
+    let (mut vm, log) = machine(GUEST);
+    for _ in 0..5 {
+        vm.step().unwrap();
+    }
+    let flags = CARRY | NEGATIVE | INTERRUPT_ENABLE;
+    assert_eq!(vm.get_register(Registers::Flags), flags);
+    let back = vm.pc() + 1;
+
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), labels(GUEST).unwrap()["handler"]);
+    // interrupts are off in the handler, Flags and the return address are on the stack
+    assert_eq!(vm.get_register(Registers::Flags), CARRY | NEGATIVE);
+    assert_eq!(vm.bus.peek(0xFFFE), Some(flags as u8));
+    assert_eq!(vm.bus.peek(0xFFFC), Some(back as u8));
+    assert_eq!(vm.bus.peek(0xFFFD), Some((back >> 8) as u8));
+
+    vm.step().unwrap();
+    assert_eq!(vm.get_register(Registers::Flags), ZERO);
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), back);
+    assert_eq!(vm.get_register(Registers::Flags), flags);
+    assert_eq!(vm.sp(), 0xFFFF);
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn interrupt_without_a_vector_or_with_interrupts_off_goes_to_the_host() {
+    let (mut vm, log) = machine(GUEST);
+    while !vm.halt {
+        vm.step().unwrap();
+    }
+    // vector 2 is 0, and the second Interrupt 1 comes after Di
+    assert_eq!(*log.borrow(), [2, 1]);
+    assert_eq!(vm.get_register(Registers::Flags), CARRY | NEGATIVE);
+}
+
+#[test]
+fn vector_table_can_move() {
+    let (mut vm, log) = machine("Ei\nInterrupt 0\nhandler:\nHalt");
+    vm.set_ivt(0x3000);
+    vm.bus.write(0x3000, 3).unwrap();
+    vm.bus.write(0xEFE0, 0xFF).unwrap();
+    while !vm.halt {
+        vm.step().unwrap();
+    }
+    // right after the Halt of the handler
+    assert_eq!(vm.pc(), 5);
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn handlers_keep_their_state() {
+    let mut vm = Machine::new();
+    vm.bus
+        .load(&assemble("Interrupt 1\nInterrupt 1\nInterrupt 1").unwrap())
+        .unwrap();
+    let mut count = 0;
+    vm.define_interrupt(1, move |vm: &mut Machine| {
+        count += 1;
+        vm.set_register(Registers::A, count);
+        Ok(())
+    });
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.get_register(Registers::A), 3);
+}
+
+/// A handler putting `value` in B.
+fn puts(value: u16) -> impl FnMut(&mut Machine) -> Result<(), VmError> {
+    move |vm: &mut Machine| {
+        vm.set_register(Registers::B, value);
+        Ok(())
+    }
+}
+
+#[test]
+fn define_and_remove_hand_back_the_old_handler() {
+    let mut vm = Machine::new();
+    vm.bus.load(&assemble("Interrupt 4").unwrap()).unwrap();
+    assert!(vm.define_interrupt(4, puts(1)).is_none());
+    let mut old = vm.define_interrupt(4, puts(2)).unwrap();
+    old.handle(&mut vm).unwrap();
+    assert_eq!(vm.get_register(Registers::B), 1);
+
+    let mut removed = vm.remove_interrupt(4).unwrap();
+    removed.handle(&mut vm).unwrap();
+    assert_eq!(vm.get_register(Registers::B), 2);
+    assert!(vm.remove_interrupt(4).is_none());
+    assert!(vm.remove_interrupt(16).is_none());
+    assert!(matches!(
+        vm.step(),
+        Err(VmError::UnknownInterrupt {
+            pc: 0,
+            signal: 4,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn handler_can_replace_itself() {
+    let mut vm = Machine::new();
+    vm.bus
+        .load(&assemble("Interrupt 5\nInterrupt 5\nInterrupt 5").unwrap())
+        .unwrap();
+    vm.define_interrupt(5, |vm: &mut Machine| {
+        vm.set_register(Registers::B, 1);
+        // the first run swaps in the handler for the next ones
+        vm.define_interrupt(5, puts(2));
+        Ok(())
+    });
+    vm.step().unwrap();
+    assert_eq!(vm.get_register(Registers::B), 1);
+    vm.set_register(Registers::B, 0);
+    vm.step().unwrap();
+    assert_eq!(vm.get_register(Registers::B), 2);
+    vm.set_register(Registers::B, 0);
+    vm.step().unwrap();
+    assert_eq!(vm.get_register(Registers::B), 2);
+}
@@ -0,0 +1,171 @@
+use vm::{
+    assembler::assemble, ALUOperation, Address, Instruction, JumpCondition, JumpTarget, Registers,
+    VmError, MAX_INSTRUCTION_SIZE,
+};
+
+fn registers() -> impl Iterator<Item = Registers> + Clone {
+    (0..16).filter_map(Registers::from_u8_custom)
+}
+
+fn addresses() -> Vec<Address> {
+    let narrow: Vec<Registers> = registers().filter(|reg| (*reg as u8) < 8).collect();
+    let mut addresses = Vec::new();
+    for &high in &narrow {
+        for &low in &narrow {
+            addresses.push(Address::Pair(high, low));
+            addresses.push(Address::PairPostIncrement(high, low));
+        }
+        addresses.push(Address::PostIncrement(high));
+        for offset in [i8::MIN, -1, 0, 1, i8::MAX] {
+            addresses.push(Address::Offset(high, offset));
+        }
+    }
+    addresses
+}
+
+/// Every instruction that has an encoding, with a handful of values for the wide operands.
+fn instructions() -> Vec<Instruction> {
+    let bytes = [0x00, 0x01, 0x7F, 0x80, 0xFF];
+    let words = [0x0000, 0x00FF, 0x1234, 0xFF00, 0xFFFF];
+    let operations: Vec<ALUOperation> = (0..16).filter_map(ALUOperation::from_u8_custom).collect();
+    let conditions: Vec<JumpCondition> =
+        (0..16).filter_map(JumpCondition::from_u8_custom).collect();
+
+    let mut instructions = vec![
+        Instruction::Nop,
+        Instruction::AddStack,
+        Instruction::Ret,
+        Instruction::Leave,
+        Instruction::Halt,
+        Instruction::Iret,
+        Instruction::Ei,
+        Instruction::Di,
+    ];
+    for value in bytes {
+        instructions.push(Instruction::Push(value));
+        instructions.push(Instruction::Enter(value));
+    }
+    for reg in registers() {
+        instructions.push(Instruction::PopRegister(reg));
+        instructions.push(Instruction::PushRegister(reg));
+        for value in words.into_iter().filter(|value| *value <= reg.mask()) {
+            instructions.push(Instruction::LoadImmediate(reg, value));
+        }
+        for other in registers() {
+            instructions.push(Instruction::Move(reg, other));
+            for &operation in &operations {
+                instructions.push(Instruction::ALU(operation, reg, other));
+            }
+        }
+        for &operation in &operations {
+            for value in bytes {
+                instructions.push(Instruction::ALUImmediate(operation, reg, value));
+            }
+        }
+    }
+    for reg in registers().filter(|reg| (*reg as u8) < 8) {
+        for address in words {
+            instructions.push(Instruction::LoadMemory(reg, address));
+            instructions.push(Instruction::Store(reg, address));
+        }
+        for address in addresses() {
+            instructions.push(Instruction::LoadIndirect(reg, address));
+            instructions.push(Instruction::StoreIndirect(reg, address));
+        }
+    }
+    for address in words {
+        instructions.push(Instruction::Jump(JumpTarget::Address(address)));
+        instructions.push(Instruction::Call(JumpTarget::Address(address)));
+        for &condition in &conditions {
+            instructions.push(Instruction::JumpConditional(
+                condition,
+                JumpTarget::Address(address),
+            ));
+        }
+    }
+    for signal in 0..16 {
+        instructions.push(Instruction::Interrupt(signal));
+    }
+    instructions
+}
+
+#[test]
+fn longest_instruction() {
+    let longest = instructions().iter().map(Instruction::size).max();
+    assert_eq!(longest, Some(MAX_INSTRUCTION_SIZE));
+}
+
+#[test]
+fn encode_then_decode() {
+    for instruction in instructions() {
+        let bytes = instruction.encode().unwrap();
+        assert_eq!(bytes.len(), instruction.size() as usize, "{instruction:?}");
+        assert_eq!(
+            Instruction::decode(&bytes),
+            Ok((instruction.clone(), bytes.len())),
+            "{bytes:02X?}"
+        );
+    }
+}
+
+#[test]
+fn display_assembles_back() {
+    for instruction in instructions() {
+        assert_eq!(
+            assemble(&instruction.to_string()),
+            instruction.encode(),
+            "{instruction}"
+        );
+    }
+}
+
+#[test]
+fn decode_then_encode() {
+    // the first two bytes pick the instruction and its operands, the tail only fills in values
+    for tail in [[0x00, 0x00], [0x5A, 0xA5], [0xFF, 0xFF]] {
+        for prefix in 0..=u16::MAX {
+            let bytes = [(prefix >> 8) as u8, prefix as u8, tail[0], tail[1]];
+            let Ok((instruction, size)) = Instruction::decode(&bytes) else {
+                continue;
+            };
+            assert_eq!(
+                instruction.encode().as_deref(),
+                Ok(&bytes[..size]),
+                "{instruction:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn truncated_instructions_fail_to_decode() {
+    for instruction in instructions() {
+        let bytes = instruction.encode().unwrap();
+        for size in 0..bytes.len() {
+            assert!(
+                Instruction::decode(&bytes[..size]).is_err(),
+                "{instruction:?} decoded from {:02X?}",
+                &bytes[..size]
+            );
+        }
+    }
+}
+
+#[test]
+fn out_of_range_operands_fail_to_encode() {
+    let unencodable = [
+        Instruction::Interrupt(16),
+        Instruction::LoadImmediate(Registers::A, 0x100),
+        Instruction::LoadMemory(Registers::AB, 0x1000),
+        Instruction::Store(Registers::CD, 0x1000),
+        Instruction::LoadIndirect(Registers::A, Address::Pair(Registers::AB, Registers::C)),
+        Instruction::StoreIndirect(Registers::AB, Address::PostIncrement(Registers::C)),
+        Instruction::Jump(JumpTarget::Label("loop".to_string())),
+    ];
+    for instruction in unencodable {
+        assert!(
+            matches!(instruction.encode(), Err(VmError::Encode { .. })),
+            "{instruction:?}"
+        );
+    }
+}
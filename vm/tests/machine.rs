@@ -0,0 +1,408 @@
+use vm::{
+    assembler::assemble,
+    devices::{
+        bank::BANK_BASE,
+        console::SharedBuffer,
+        timer::{TIMER_BASE, TIMER_CONTROL, TIMER_COUNTER, TIMER_ENABLE, TIMER_SIZE},
+        BankedMemory, Timer,
+    },
+    Access, Bus, Instruction, JsonTracer, JumpTarget, Machine, Permissions, Registers, TextTracer,
+    TraceEvent, Tracer, VmError, WatchAction, CHECKPOINT_INTERVAL,
+};
+
+/// A machine with `source` assembled and loaded at 0.
+fn machine(source: &str) -> Machine {
+    let mut vm = Machine::new();
+    vm.bus.load(&assemble(source).unwrap()).unwrap();
+    vm
+}
+
+#[test]
+fn wide_store_is_seen_by_watchpoints_journal_and_tracer() {
+    let mut vm = machine("LoadImmediate BP 0x1234\nStore BP 0x4000");
+    let trace = SharedBuffer::new();
+    vm.set_tracer(TextTracer::new(trace.clone()));
+    vm.start_journal(16).unwrap();
+    vm.watch(0x4001, 1, Access::Write, WatchAction::Stop);
+
+    vm.step().unwrap();
+    assert_eq!(
+        vm.step(),
+        Err(VmError::Watchpoint {
+            pc: 3,
+            addr: 0x4001,
+            access: Access::Write,
+            old: Some(0x00),
+            new: 0x12,
+        })
+    );
+    let trace = String::from_utf8(trace.bytes()).unwrap();
+    assert!(trace.contains("memory    0x4000 <- 0x34"));
+    assert!(trace.contains("memory    0x4001 <- 0x12"));
+
+    assert_eq!(vm.step_back(), Ok(true));
+    assert_eq!(vm.bus.peek(0x4000), Some(0x00));
+    assert_eq!(vm.bus.peek(0x4001), Some(0x00));
+    assert_eq!(vm.pc(), 3);
+}
+
+#[test]
+fn json_tracer_writes_one_object_per_event() {
+    let mut vm = machine("Ei\nStore A 0x4000\nInterrupt 1");
+    vm.define_interrupt(1, |_: &mut Machine| Ok(()));
+    let trace = SharedBuffer::new();
+    vm.set_tracer(JsonTracer::new(trace.clone()));
+    vm.set_register(Registers::A, 0x2A);
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+
+    let trace = String::from_utf8(trace.bytes()).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"event":"fetch","addr":0,"byte":224}"#,
+            r#"{"event":"fetch","addr":1,"byte":3}"#,
+            r#"{"event":"decode","pc":0,"instruction":"Ei"}"#,
+            r#"{"event":"register","register":"PC","old":0,"new":2}"#,
+            r#"{"event":"register","register":"Flags","old":0,"new":16}"#,
+            r#"{"event":"fetch","addr":2,"byte":112}"#,
+            r#"{"event":"fetch","addr":3,"byte":64}"#,
+            r#"{"event":"fetch","addr":4,"byte":0}"#,
+            r#"{"event":"decode","pc":2,"instruction":"Store(A, 16384)"}"#,
+            r#"{"event":"memory","addr":16384,"value":42}"#,
+            r#"{"event":"register","register":"PC","old":2,"new":5}"#,
+            r#"{"event":"fetch","addr":5,"byte":241}"#,
+            r#"{"event":"decode","pc":5,"instruction":"Interrupt(1)"}"#,
+            r#"{"event":"interrupt","signal":1,"vector":null}"#,
+            r#"{"event":"register","register":"PC","old":5,"new":6}"#,
+        ]
+    );
+}
+
+#[test]
+fn json_tracer_escapes_strings() {
+    let trace = SharedBuffer::new();
+    let mut tracer = JsonTracer::new(trace.clone());
+    let instruction = Instruction::Jump(JumpTarget::Label(r#"a"b\c"#.to_string()));
+    tracer.trace(&TraceEvent::Decode {
+        pc: 0,
+        instruction: &instruction,
+    });
+    tracer.trace(&TraceEvent::Interrupt {
+        signal: 2,
+        vector: Some(0x1234),
+    });
+    assert_eq!(
+        String::from_utf8(trace.bytes()).unwrap(),
+        concat!(
+            r#"{"event":"decode","pc":0,"instruction":"Jump(Label(\"a\\\"b\\\\c\"))"}"#,
+            "\n",
+            r#"{"event":"interrupt","signal":2,"vector":4660}"#,
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn unhandled_irq_is_reported_once_and_lowered() {
+    let mut vm = machine("Ei\nNop\nNop");
+    vm.step().unwrap();
+    vm.irq.raise(3);
+
+    assert_eq!(vm.step(), Err(VmError::UnhandledIrq { pc: 2, line: 3 }));
+    assert!(!vm.irq.is_pending(3));
+    assert_eq!(vm.pc(), 2);
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 3);
+}
+
+#[test]
+fn faulting_enter_leaves_max_stack_depth_alone() {
+    let mut vm = machine("Enter 20");
+    vm.set_stack(0xffff, 0xfff0);
+
+    assert!(matches!(vm.step(), Err(VmError::Stack { pc: 0, .. })));
+    assert_eq!(vm.sp(), 0xffff);
+    assert_eq!(vm.max_stack_depth(), 0);
+}
+
+#[test]
+fn faulting_wide_store_writes_neither_byte() {
+    let mut vm = machine("LoadImmediate BP 0x1234\nStore BP 0x4000\nStore BP 0x3000");
+    vm.bus.protection.protect(0x4001, 1, Permissions::READ);
+    vm.start_journal(16).unwrap();
+
+    vm.step().unwrap();
+    assert!(matches!(
+        vm.step(),
+        Err(VmError::Protection {
+            pc: 3,
+            addr: 0x4001,
+            access: Access::Write,
+            ..
+        })
+    ));
+    assert_eq!(vm.bus.peek(0x4000), Some(0x00));
+    assert_eq!(vm.pc(), 3);
+
+    // moved past it by hand, the journal steps back over the store that did run
+    vm.set_register(Registers::PC, 6);
+    vm.step().unwrap();
+    assert_eq!(vm.bus.peek(0x3000), Some(0x34));
+    assert_eq!(vm.step_back(), Ok(true));
+    assert_eq!(vm.bus.peek(0x3000), Some(0x00));
+    assert_eq!(vm.bus.peek(0x4000), Some(0x00));
+}
+
+/// RAM refusing every write to 0x4001.
+struct ReadOnlyByte(Vec<u8>);
+
+impl Bus for ReadOnlyByte {
+    fn read(&mut self, addr: u16) -> Result<u8, VmError> {
+        Ok(self.0[addr as usize])
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), VmError> {
+        if addr == 0x4001 {
+            return Err(VmError::Bus {
+                pc: 0,
+                opcode: 0,
+                addr,
+                access: Access::Write,
+            });
+        }
+        self.0[addr as usize] = value;
+        Ok(())
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.0[addr as usize])
+    }
+}
+
+#[test]
+fn wide_store_the_bus_refuses_half_of_is_undone() {
+    let mut vm = Machine::with_bus(ReadOnlyByte(vec![0; 0x10000]));
+    vm.bus
+        .load(&assemble("LoadImmediate BP 0x1234\nStore BP 0x4000").unwrap())
+        .unwrap();
+    vm.step().unwrap();
+    assert!(matches!(vm.step(), Err(VmError::Bus { addr: 0x4001, .. })));
+    assert_eq!(vm.bus.peek(0x4000), Some(0x00));
+}
+
+/// Maps a timer and `banks` banks of 16 bytes, the select port at `BANK_BASE + 16`.
+fn map_devices(vm: &mut Machine, banks: usize) {
+    vm.bus
+        .map(TIMER_BASE, TIMER_SIZE, Box::new(Timer::new(0)))
+        .unwrap();
+    let banks = BankedMemory::new(banks, 16);
+    vm.bus
+        .map(BANK_BASE, banks.size(), Box::new(banks))
+        .unwrap();
+}
+
+#[test]
+fn snapshot_round_trip_includes_devices() {
+    let mut vm = machine("LoadImmediate A 7\nStore A 0x1000\nNop\nNop\nNop");
+    map_devices(&mut vm, 4);
+    vm.bus.write(TIMER_BASE, 100).unwrap();
+    vm.bus
+        .write(TIMER_BASE + TIMER_CONTROL, TIMER_ENABLE)
+        .unwrap();
+    vm.bus.write(BANK_BASE + 16, 2).unwrap();
+    vm.bus.write(BANK_BASE, 0xAB).unwrap();
+    vm.step().unwrap();
+    vm.step().unwrap();
+    let snapshot = vm.snapshot().unwrap();
+    let state = vm.state();
+
+    vm.step().unwrap();
+    vm.bus.write(0x1000, 0).unwrap();
+    vm.bus.write(BANK_BASE, 0).unwrap();
+    vm.bus.write(BANK_BASE + 16, 1).unwrap();
+    vm.bus.write(TIMER_BASE + TIMER_CONTROL, 0).unwrap();
+
+    vm.restore(&snapshot).unwrap();
+    assert_eq!(vm.state(), state);
+    assert_eq!(vm.cycles(), 2);
+    assert_eq!(vm.bus.peek(0x1000), Some(7));
+    assert_eq!(vm.bus.peek(BANK_BASE + 16), Some(2));
+    assert_eq!(vm.bus.peek(BANK_BASE), Some(0xAB));
+    assert_eq!(vm.bus.peek(TIMER_BASE + TIMER_CONTROL), Some(TIMER_ENABLE));
+    assert_eq!(vm.bus.peek(TIMER_BASE + TIMER_COUNTER), Some(98));
+    assert_eq!(vm.snapshot().unwrap(), snapshot);
+}
+
+#[test]
+fn restore_that_doesnt_fit_leaves_the_machine_alone() {
+    let mut other = Machine::new();
+    map_devices(&mut other, 4);
+    other.bus.write(0x1000, 7).unwrap();
+    other.bus.write(TIMER_BASE, 100).unwrap();
+    let snapshot = other.snapshot().unwrap();
+
+    // same layout, but the banked memory is smaller so it refuses its state
+    let mut vm = Machine::new();
+    map_devices(&mut vm, 2);
+    vm.bus.write(TIMER_BASE, 5).unwrap();
+    let before = vm.snapshot().unwrap();
+    assert!(matches!(
+        vm.restore(&snapshot),
+        Err(VmError::Snapshot { .. })
+    ));
+    assert_eq!(vm.snapshot().unwrap(), before);
+    assert_eq!(vm.bus.peek(TIMER_BASE), Some(5));
+    assert_eq!(vm.bus.peek(0x1000), Some(0));
+}
+
+#[test]
+fn restore_refuses_registers_wider_than_they_are() {
+    let mut vm = machine("LoadImmediate A 7");
+    let mut snapshot = vm.snapshot().unwrap();
+    // the high byte of A, right after the magic and the version
+    snapshot[7] = 0x01;
+    vm.step().unwrap();
+    assert!(matches!(
+        vm.restore(&snapshot),
+        Err(VmError::Snapshot { .. })
+    ));
+    assert_eq!(vm.get_register(Registers::AB), 0x0700);
+}
+
+/// Memory that can only be read the way the program reads it.
+struct NoPeek;
+
+impl Bus for NoPeek {
+    fn read(&mut self, _addr: u16) -> Result<u8, VmError> {
+        panic!("a snapshot shouldn't read memory")
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) -> Result<(), VmError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn snapshot_of_a_bus_that_cant_peek_fails() {
+    let vm = Machine::with_bus(NoPeek);
+    assert!(matches!(vm.snapshot(), Err(VmError::Snapshot { .. })));
+}
+
+const COUNTER: &str = "
+LoadImmediate A 0
+loop:
+    AddI A 1
+    Store A 0x2000
+    PushRegister A
+    PopRegister B
+    Jump loop
+";
+
+/// What a test can compare to tell the states of the counter program apart.
+fn observe(vm: &Machine) -> ([u16; 8], u64, Option<u8>, Option<u8>) {
+    (
+        vm.registers,
+        vm.cycles(),
+        vm.bus.peek(0x2000),
+        vm.bus.peek(0xfffe),
+    )
+}
+
+#[test]
+fn step_back_across_checkpoints() {
+    let mut vm = machine(COUNTER);
+    let kept = CHECKPOINT_INTERVAL + 10;
+    vm.start_journal(kept).unwrap();
+    let mut states = vec![observe(&vm)];
+    for _ in 0..3 * CHECKPOINT_INTERVAL + 7 {
+        vm.step().unwrap();
+        states.push(observe(&vm));
+    }
+    assert!(vm.journal_len() >= kept);
+
+    let mut undone = 0;
+    while vm.step_back().unwrap() {
+        undone += 1;
+        assert_eq!(observe(&vm), states[states.len() - 1 - undone]);
+    }
+    // the oldest checkpoints were dropped, the history starts at one of the others
+    assert!(undone >= kept && undone < states.len() - 1);
+    assert_eq!(vm.cycles() as usize % CHECKPOINT_INTERVAL, 0);
+    assert_eq!(vm.journal_len(), 0);
+
+    // and forward again from there, the same way
+    let at = states.len() - 1 - undone;
+    for state in &states[at + 1..at + 20] {
+        vm.step().unwrap();
+        assert_eq!(observe(&vm), *state);
+    }
+}
+
+#[test]
+fn run_back_to_a_pc() {
+    let mut vm = machine(COUNTER);
+    vm.start_journal(100).unwrap();
+    for _ in 0..50 {
+        vm.step().unwrap();
+    }
+    let store = 5;
+    let counter = vm.bus.peek(0x2000).unwrap();
+
+    assert_eq!(vm.run_back_to(store), Ok(true));
+    assert_eq!(vm.pc(), store);
+    // the Store hasn't run yet, memory holds the previous count
+    assert_eq!(vm.bus.peek(0x2000), Some(counter - 1));
+    assert_eq!(vm.run_back_to(0x1234), Ok(false));
+    assert_eq!(vm.cycles(), 0);
+    assert_eq!(vm.pc(), 0);
+}
+
+#[test]
+fn journal_needs_a_bus_that_can_peek() {
+    let mut vm = Machine::with_bus(NoPeek);
+    assert!(matches!(
+        vm.start_journal(10),
+        Err(VmError::Snapshot { .. })
+    ));
+}
+
+/// RAM that can peek everything but 0x2000.
+struct Blind(Vec<u8>);
+
+impl Bus for Blind {
+    fn read(&mut self, addr: u16) -> Result<u8, VmError> {
+        Ok(self.0[addr as usize])
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> Result<(), VmError> {
+        self.0[addr as usize] = value;
+        Ok(())
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.0[addr as usize]).filter(|_| addr != 0x2000)
+    }
+}
+
+#[test]
+fn step_back_over_a_write_it_cant_undo_fails() {
+    let mut vm = Machine::with_bus(Blind(vec![0; 0x10000]));
+    vm.bus.load(&assemble(COUNTER).unwrap()).unwrap();
+    vm.start_journal(10).unwrap();
+    for _ in 0..4 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.step_back(), Ok(true));
+    let before = observe(&vm);
+    assert_eq!(
+        vm.step_back(),
+        Err(VmError::Irreversible {
+            pc: 5,
+            addr: 0x2000
+        })
+    );
+    assert_eq!(observe(&vm), before);
+}
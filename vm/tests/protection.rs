@@ -0,0 +1,74 @@
+use std::io;
+
+use vm::{
+    assembler::assemble,
+    devices::{console::SharedBuffer, Console},
+    Access, Machine, Permissions, VmError,
+};
+
+/// The machine the binaries use, with `source` loaded as read+execute code.
+fn machine(source: &str) -> Machine {
+    let console = Console::new(io::empty(), SharedBuffer::new());
+    let mut vm = Machine::standard(console).unwrap();
+    vm.load_program(&assemble(source).unwrap()).unwrap();
+    vm
+}
+
+/// Where and how `step` faulted on protection, `None` if it didn't.
+fn fault(vm: &mut Machine) -> Option<(u16, u16, Access)> {
+    match vm.step() {
+        Err(VmError::Protection {
+            pc, addr, access, ..
+        }) => Some((pc, addr, access)),
+        _ => None,
+    }
+}
+
+#[test]
+fn store_into_code() {
+    let mut vm = machine("LoadImmediate A 0x42\nStore A 0x0000");
+    let opcode = vm.bus.peek(0x0000);
+    vm.step().unwrap();
+    assert_eq!(fault(&mut vm), Some((2, 0x0000, Access::Write)));
+    assert_eq!(vm.bus.peek(0x0000), opcode);
+    assert_eq!(vm.pc(), 2);
+}
+
+#[test]
+fn fetch_from_the_stack() {
+    let mut vm = machine("Jump 0xF800");
+    vm.step().unwrap();
+    assert_eq!(fault(&mut vm), Some((0xF800, 0xF800, Access::Execute)));
+}
+
+#[test]
+fn push_into_memory_that_cant_be_written() {
+    let mut vm = machine("Push 1");
+    vm.bus.protection.protect(0xFFFE, 1, Permissions::READ);
+    assert_eq!(fault(&mut vm), Some((0, 0xFFFE, Access::Write)));
+    assert_eq!(vm.sp(), 0xFFFF);
+}
+
+#[test]
+fn pop_from_memory_that_cant_be_read() {
+    let mut vm = machine("Push 1\nPopRegister A");
+    vm.step().unwrap();
+    vm.bus.protection.protect(0xFFFE, 1, Permissions::NONE);
+    assert_eq!(fault(&mut vm), Some((2, 0xFFFE, Access::Read)));
+    assert_eq!(vm.sp(), 0xFFFE);
+}
+
+#[test]
+fn program_protected_up_to_0xffff_bytes() {
+    let mut vm = Machine::new();
+    assert_eq!(
+        vm.load_program(&vec![0; 0x10000]),
+        Err(VmError::ProgramSize { size: 0x10000 })
+    );
+    vm.load_program(&vec![0; 0xFFFF]).unwrap();
+    assert_eq!(
+        vm.bus.protection.permissions(0xFFFE),
+        Permissions::READ_EXECUTE
+    );
+    assert_eq!(vm.bus.protection.permissions(0xFFFF), Permissions::ALL);
+}
@@ -0,0 +1,74 @@
+use vm::{assembler::assemble, Machine, Registers, StackFault, VmError};
+
+/// A machine with `source` loaded and a 4 byte stack from 0x1004 down to 0x1000.
+fn machine(source: &str) -> Machine {
+    let mut vm = Machine::new();
+    vm.bus.load(&assemble(source).unwrap()).unwrap();
+    vm.set_stack(0x1004, 0x1000);
+    vm
+}
+
+fn fault(vm: &mut Machine) -> (u16, u16, StackFault) {
+    match vm.step() {
+        Err(VmError::Stack { pc, sp, fault, .. }) => (pc, sp, fault),
+        other => panic!("expected a stack fault, got {other:?}"),
+    }
+}
+
+#[test]
+fn push_down_to_the_limit() {
+    let mut vm = machine("Push 1\nPush 2\nPush 3\nPush 4\nPush 5");
+    for _ in 0..4 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.sp(), 0x1000);
+    assert_eq!(vm.bus.peek(0x1000), Some(4));
+    assert_eq!(fault(&mut vm), (8, 0x1000, StackFault::Overflow));
+    assert_eq!(vm.sp(), 0x1000);
+    assert_eq!(vm.bus.peek(0x0fff), Some(0));
+}
+
+#[test]
+fn wide_push_with_one_byte_left_overflows() {
+    let mut vm = machine("Push 1\nPush 2\nPush 3\nPushRegister BP");
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    assert_eq!(fault(&mut vm), (6, 0x1001, StackFault::Overflow));
+    assert_eq!(vm.bus.peek(0x1000), Some(0));
+}
+
+#[test]
+fn pop_from_an_empty_stack() {
+    let mut vm = machine("Push 1\nPopRegister A\nPopRegister A");
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.get_register(Registers::A), 1);
+    assert_eq!(fault(&mut vm), (3, 0x1004, StackFault::Underflow));
+}
+
+#[test]
+fn pop_below_the_limit() {
+    let mut vm = machine("LoadImmediate SP 0x10\nPopRegister A");
+    vm.step().unwrap();
+    assert_eq!(fault(&mut vm), (3, 0x10, StackFault::Overflow));
+    assert_eq!(vm.get_register(Registers::A), 0);
+
+    // BP = 0 points SP at the code
+    let mut vm = machine("Leave");
+    assert_eq!(fault(&mut vm), (0, 0, StackFault::Overflow));
+    assert_eq!(vm.sp(), 0x1004);
+}
+
+#[test]
+fn max_stack_depth_keeps_the_deepest() {
+    let mut vm = machine("Push 1\nPush 2\nPush 3\nPopRegister A\nPopRegister A\nPush 4");
+    for _ in 0..6 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.stack_depth(), 2);
+    assert_eq!(vm.max_stack_depth(), 3);
+
+    vm.set_stack(0x2000, 0x1f00);
+    assert_eq!(vm.max_stack_depth(), 0);
+}